@@ -0,0 +1,37 @@
+use brainfuck_rs::{
+    codegen::rust,
+    compiler::Compiler,
+    config::{Config, Overflow},
+    optimizer::optimize,
+};
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, LitStr};
+
+/// Compile a brainfuck program into a `fn(&[u8]) -> Vec<u8>`
+/// taking the program input and returning its output.
+///
+/// ```
+/// use brainfuck_macros::brainfuck;
+///
+/// let swap = brainfuck!(",>,.<.");
+/// assert_eq!(swap(b"ab"), b"ba");
+/// ```
+#[proc_macro]
+pub fn brainfuck(input: TokenStream) -> TokenStream {
+    let program = parse_macro_input!(input as LitStr);
+
+    let exprs = match Compiler::new(program.value().chars()).compile() {
+        Ok(exprs) => optimize(exprs, Overflow::Wrap),
+        Err(error) => {
+            return syn::Error::new(program.span(), error)
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let function = rust::emit(&exprs, &Config::default()).expect("default config is supported");
+
+    format!("{{ {function} run }}")
+        .parse()
+        .expect("generated code is valid rust")
+}
@@ -0,0 +1,17 @@
+use brainfuck_macros::brainfuck;
+
+#[test]
+fn should_print_hello_world() {
+    let hello = brainfuck!(
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."
+    );
+
+    assert_eq!(hello(b""), b"Hello World!\n");
+}
+
+#[test]
+fn should_read_input() {
+    let reverse = brainfuck!(">,[>,]<[.<]");
+
+    assert_eq!(reverse(b"abc"), b"cba");
+}
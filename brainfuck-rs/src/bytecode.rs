@@ -0,0 +1,347 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compiler::{BitOp, Expr, Span},
+    error::{Error, RuntimeErrorKind},
+    on_call, shift_instruction, BrainFuck,
+};
+
+/// A single flat instruction, see [`Expr`] for the meaning of each.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Move(i32),
+    Add {
+        offset: i32,
+        value: u32,
+    },
+    Sub {
+        offset: i32,
+        value: u32,
+    },
+    Input,
+    Output,
+    SetZero,
+    Scan(i32),
+    MulAdd {
+        offset: i32,
+        factor: i32,
+    },
+
+    /// `[`, jump to the instruction after the matching `]` if the cell is zero.
+    JumpIfZero(u32),
+
+    /// `]`, jump to the instruction after the matching `[` if the cell is not zero.
+    JumpIfNotZero(u32),
+
+    /// `(`, define the procedure numbered by the cell as the instructions
+    /// that follow, then jump to the instruction after the matching `)`.
+    Define(u32),
+
+    /// `)`, go back to the instruction after the latest call.
+    Return,
+
+    /// `:`, call the procedure numbered by the cell.
+    Call,
+
+    End,
+    Store,
+    Load,
+    Bitwise(BitOp),
+}
+
+/// pbrain procedures defined while running a chunk, and the calls in progress.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Calls {
+    /// First instruction of each procedure, by number.
+    procedures: BTreeMap<u32, usize>,
+
+    /// Where each call in progress continues, innermost last.
+    returns: Vec<usize>,
+}
+
+impl Calls {
+    /// Number of calls in progress.
+    pub fn depth(&self) -> usize {
+        self.returns.len()
+    }
+}
+
+/// Instructions with the source span each one was compiled from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Chunk {
+    pub ops: Vec<Op>,
+    pub spans: Vec<Span>,
+}
+
+/// Flatten an expression tree into instructions.
+///
+/// `spans` are the spans of `exprs` as recorded by the compiler,
+/// they may be empty when unknown.
+pub fn lower(exprs: &[Expr], spans: &[Span]) -> Chunk {
+    let mut chunk = Chunk::default();
+    let mut spans = spans.iter();
+
+    chunk.lower_block(exprs, &mut spans);
+    chunk
+}
+
+impl Chunk {
+    fn lower_block(&mut self, exprs: &[Expr], spans: &mut std::slice::Iter<Span>) {
+        for expr in exprs {
+            let span = spans.next().copied().unwrap_or_default();
+
+            let op = match expr {
+                Expr::IncPtr(n) => Op::Move(*n as i32),
+                Expr::DecPtr(n) => Op::Move(-(*n as i32)),
+                Expr::IncData(value) => Op::Add {
+                    offset: 0,
+                    value: *value,
+                },
+                Expr::DecData(value) => Op::Sub {
+                    offset: 0,
+                    value: *value,
+                },
+                Expr::IncDataAt { offset, value } => Op::Add {
+                    offset: *offset,
+                    value: *value,
+                },
+                Expr::DecDataAt { offset, value } => Op::Sub {
+                    offset: *offset,
+                    value: *value,
+                },
+                Expr::Input => Op::Input,
+                Expr::Output => Op::Output,
+                Expr::SetZero => Op::SetZero,
+                Expr::Scan(step) => Op::Scan(*step),
+                Expr::MulAdd { offset, factor } => Op::MulAdd {
+                    offset: *offset,
+                    factor: *factor,
+                },
+                Expr::Call => Op::Call,
+                Expr::End => Op::End,
+                Expr::Store => Op::Store,
+                Expr::Load => Op::Load,
+                Expr::Bitwise(op) => Op::Bitwise(*op),
+                Expr::Loop(body) => {
+                    let open = self.ops.len();
+                    self.write(Op::JumpIfZero(0), span);
+
+                    self.lower_block(body, spans);
+
+                    let close = self.ops.len();
+                    let bracket = Span {
+                        start: span.end.saturating_sub(1),
+                        end: span.end,
+                    };
+                    self.write(Op::JumpIfNotZero(open as u32 + 1), bracket);
+
+                    // Patch the forward jump now that the end is known.
+                    self.ops[open] = Op::JumpIfZero(close as u32 + 1);
+                    continue;
+                }
+                Expr::Procedure(body) => {
+                    let define = self.ops.len();
+                    self.write(Op::Define(0), span);
+
+                    self.lower_block(body, spans);
+
+                    let paren = Span {
+                        start: span.end.saturating_sub(1),
+                        end: span.end,
+                    };
+                    self.write(Op::Return, paren);
+
+                    self.ops[define] = Op::Define(self.ops.len() as u32);
+                    continue;
+                }
+            };
+
+            self.write(op, span);
+        }
+    }
+
+    fn write(&mut self, op: Op, span: Span) {
+        self.ops.push(op);
+        self.spans.push(span);
+    }
+}
+
+/// Copy `ops`, which start at `start` in their chunk, with jumps counted from their own start.
+fn rebase(ops: &[Op], start: usize) -> Rc<[Op]> {
+    let start = start as u32;
+
+    ops.iter()
+        .map(|op| match *op {
+            Op::JumpIfZero(target) => Op::JumpIfZero(target - start),
+            Op::JumpIfNotZero(target) => Op::JumpIfNotZero(target - start),
+            Op::Define(end) => Op::Define(end - start),
+            op => op,
+        })
+        .collect()
+}
+
+impl<R: Read, W: Write> BrainFuck<R, W> {
+    /// Run flat instructions against the current tape.
+    ///
+    /// pbrain procedures stay defined for later calls, as they do when walking the tree.
+    pub fn execute_ops(&mut self, ops: &[Op]) -> Result<(), Error> {
+        let mut pc = 0;
+        let mut calls = Calls::default();
+
+        while let Some(op) = ops.get(pc) {
+            let result = match *op {
+                Op::Define(end) => {
+                    let body = rebase(&ops[pc + 1..end as usize - 1], pc + 1);
+                    self.procedure_ops.insert(self.tape.get(), body);
+                    pc = end as usize;
+                    Ok(())
+                }
+                Op::Call => self.call_ops().map(|()| pc += 1),
+                op => self.execute_op(op, &mut pc, &mut calls),
+            };
+            result.map_err(|error| shift_instruction(error, pc))?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the procedure numbered by the current cell, which may come from an earlier program.
+    fn call_ops(&mut self) -> Result<(), Error> {
+        let cell = self.tape.get();
+        let Some(body) = self.procedure_ops.get(&cell).cloned() else {
+            return Err(self.runtime_error(RuntimeErrorKind::UndefinedProcedure, 0, cell));
+        };
+        if self.depth >= self.config.language.max_depth() {
+            return Err(self.runtime_error(RuntimeErrorKind::CallDepth, 0, cell));
+        }
+
+        self.depth += 1;
+        let result = self.execute_ops(&body);
+        self.depth -= 1;
+
+        result.map_err(on_call)
+    }
+
+    pub(crate) fn execute_op(
+        &mut self,
+        op: Op,
+        pc: &mut usize,
+        calls: &mut Calls,
+    ) -> Result<(), Error> {
+        let cell = self.tape.get();
+
+        match op {
+            Op::Move(by) => self.move_by(by as isize)?,
+            Op::Add { offset, value } => self.add_at(offset as isize, value as u64)?,
+            Op::Sub { offset, value } => self.sub_at(offset as isize, value as u64)?,
+            Op::Input => self.read_input()?,
+            Op::Output => self.write_output()?,
+            Op::SetZero => self.tape.set(0),
+            Op::MulAdd { offset, factor } => self.mul_add(offset, factor)?,
+            Op::JumpIfZero(target) if cell == 0 => {
+                *pc = target as usize;
+                return Ok(());
+            }
+            Op::JumpIfNotZero(target) if cell != 0 => {
+                *pc = target as usize;
+                return Ok(());
+            }
+            Op::JumpIfZero(_) | Op::JumpIfNotZero(_) => (),
+            Op::Scan(step) if cell != 0 => {
+                // One move at a time, so a step budget can stop a scan that never ends.
+                return self.move_by(step as isize);
+            }
+            Op::Scan(_) => (),
+            Op::Store => self.storage = cell,
+            Op::Load => self.tape.set(self.storage),
+            Op::Bitwise(op) => self.bitwise(op),
+            Op::End => {
+                // Past the end of any chunk, so nothing is left to run.
+                *pc = usize::MAX;
+                return Ok(());
+            }
+            Op::Define(end) => {
+                calls.procedures.insert(cell, *pc + 1);
+                *pc = end as usize;
+                return Ok(());
+            }
+            Op::Return => {
+                // Only a call leads into a body, so there is always somewhere to return to.
+                *pc = calls.returns.pop().unwrap_or(*pc + 1);
+                return Ok(());
+            }
+            Op::Call => {
+                let Some(start) = calls.procedures.get(&cell) else {
+                    return Err(self.runtime_error(RuntimeErrorKind::UndefinedProcedure, 0, cell));
+                };
+                if calls.depth() >= self.config.language.max_depth() {
+                    return Err(self.runtime_error(RuntimeErrorKind::CallDepth, 0, cell));
+                }
+
+                calls.returns.push(*pc + 1);
+                *pc = *start;
+                return Ok(());
+            }
+        }
+
+        *pc += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    #[test]
+    fn should_lower_loops_with_jump_targets() {
+        let exprs = Compiler::new("+[->+<].".chars()).compile().unwrap();
+        let chunk = lower(&exprs, &[]);
+
+        assert_eq!(
+            chunk.ops,
+            vec![
+                Op::Add {
+                    offset: 0,
+                    value: 1
+                },
+                Op::JumpIfZero(7),
+                Op::Sub {
+                    offset: 0,
+                    value: 1
+                },
+                Op::Move(1),
+                Op::Add {
+                    offset: 0,
+                    value: 1
+                },
+                Op::Move(-1),
+                Op::JumpIfNotZero(2),
+                Op::Output,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_map_spans_to_brackets() {
+        let source = "+[>.]";
+        let mut compiler = Compiler::new(source.chars());
+        let exprs = compiler.compile().unwrap();
+
+        let chunk = lower(&exprs, compiler.spans());
+        let spans = chunk
+            .spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec!["+", "[>.]", ">", ".", "]"]);
+    }
+}
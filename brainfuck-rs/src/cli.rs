@@ -0,0 +1,313 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use brainfuck_rs::{
+    codegen::Target,
+    config::{CellWidth, Config, Engine, EofMode, Language, Overflow, MAX_CALL_DEPTH},
+    dialect::Dialect,
+    error::DialectError,
+    formatter::Style,
+    tape::{TapeMode, TAPE_LENGTH},
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+#[clap(propagate_version = true)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Run a program
+    Run {
+        #[clap(flatten)]
+        source: Source,
+
+        #[clap(flatten)]
+        options: Options,
+
+        /// Read the program's input from a file instead of stdin.
+        #[clap(short, long, value_parser)]
+        input: Option<PathBuf>,
+
+        /// Stop after this many instructions, always on the bytecode engine.
+        #[clap(long, value_parser)]
+        max_steps: Option<u64>,
+
+        /// Where to save the state when the run stops early, always on the bytecode engine.
+        #[clap(long, value_parser)]
+        snapshot: Option<PathBuf>,
+
+        /// Continue a run saved with '--snapshot' instead, same as the resume command.
+        #[clap(long, value_parser, conflicts_with_all = &["file", "eval"])]
+        resume: Option<PathBuf>,
+    },
+
+    /// Continue a run saved with '--snapshot', also 'run --resume <path>'
+    Resume {
+        /// The saved state.
+        #[clap(value_parser)]
+        path: PathBuf,
+
+        /// Read the rest of the program's input from a file instead of stdin.
+        #[clap(short, long, value_parser)]
+        input: Option<PathBuf>,
+
+        /// Stop after this many more instructions.
+        #[clap(long, value_parser)]
+        max_steps: Option<u64>,
+
+        /// Where to save the state when the run stops early again.
+        #[clap(long, value_parser)]
+        snapshot: Option<PathBuf>,
+    },
+
+    /// Rewrite a program in another dialect
+    Convert {
+        #[clap(flatten)]
+        source: Source,
+
+        /// The dialect to write: brainfuck, ook, blub or a token table file.
+        #[clap(long, value_parser)]
+        to: String,
+    },
+
+    /// Check a program for unmatched brackets
+    Check {
+        #[clap(flatten)]
+        source: Source,
+
+        /// Commands beyond brainfuck: brainfuck, pbrain or extended.
+        #[clap(long, value_parser, default_value = "brainfuck")]
+        language: Language,
+    },
+
+    /// Lay a program out with one indentation level per loop
+    Fmt {
+        #[clap(flatten)]
+        source: Source,
+
+        /// Commands beyond brainfuck: brainfuck, pbrain or extended.
+        #[clap(long, value_parser, default_value = "brainfuck")]
+        language: Language,
+
+        /// Longest line to fill with commands.
+        #[clap(long, value_parser, default_value_t = Style::default().width)]
+        width: usize,
+
+        /// Spaces per loop level.
+        #[clap(long, value_parser, default_value_t = Style::default().indent)]
+        indent: usize,
+
+        /// Strip comments and cancelling commands instead, leaving one line.
+        #[clap(long, action)]
+        minify: bool,
+    },
+
+    /// Translate a program into asm, c, rust or wat
+    Emit {
+        /// The language to write.
+        #[clap(value_parser)]
+        target: Target,
+
+        #[clap(flatten)]
+        source: Source,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+
+    /// Step through a program, reading debugger commands from stdin
+    Debug {
+        #[clap(flatten)]
+        source: Source,
+
+        #[clap(flatten)]
+        options: Options,
+
+        /// The program's input, empty when not given.
+        #[clap(short, long, value_parser)]
+        input: Option<PathBuf>,
+    },
+
+    /// Run a program and report its hottest loops on stderr
+    Profile {
+        #[clap(flatten)]
+        source: Source,
+
+        #[clap(flatten)]
+        options: Options,
+
+        /// Read the program's input from a file instead of stdin.
+        #[clap(short, long, value_parser)]
+        input: Option<PathBuf>,
+
+        /// Number of loops to report.
+        #[clap(long, value_parser, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Write a program that prints the given text
+    Generate {
+        /// The text to print, read as bytes from stdin when missing.
+        #[clap(value_parser)]
+        text: Option<String>,
+
+        /// Read the text from a file instead.
+        #[clap(short, long, value_parser, conflicts_with = "text")]
+        input: Option<PathBuf>,
+
+        /// Try many setup loops and keep the shortest program.
+        #[clap(long, action)]
+        search: bool,
+    },
+
+    /// Run lines of brainfuck as they are typed, on one tape
+    Repl {
+        #[clap(flatten)]
+        options: Options,
+    },
+}
+
+/// Where the program comes from.
+#[derive(Args, Debug)]
+pub struct Source {
+    /// Program file, read from stdin when missing or '-'.
+    #[clap(value_parser)]
+    pub file: Option<PathBuf>,
+
+    /// Program text given on the command line.
+    #[clap(
+        short,
+        long,
+        value_parser,
+        conflicts_with = "file",
+        allow_hyphen_values = true
+    )]
+    pub eval: Option<String>,
+
+    /// The program's dialect: brainfuck, ook, blub or a token table file.
+    #[clap(long, value_parser, default_value = "brainfuck")]
+    pub dialect: String,
+}
+
+impl Source {
+    pub fn read(&self) -> io::Result<String> {
+        if let Some(program) = &self.eval {
+            return Ok(program.clone());
+        }
+
+        match &self.file {
+            Some(path) if path.as_os_str() != "-" => fs::read_to_string(path),
+            _ => {
+                let mut program = String::new();
+                io::stdin().read_to_string(&mut program)?;
+                Ok(program)
+            }
+        }
+    }
+
+    /// Whether [`Source::read`] takes the program from stdin.
+    pub fn is_stdin(&self) -> bool {
+        self.eval.is_none()
+            && self
+                .file
+                .as_ref()
+                .is_none_or(|path| path.as_os_str() == "-")
+    }
+
+    pub fn dialect(&self) -> Result<Dialect, DialectError> {
+        load_dialect(&self.dialect)
+    }
+}
+
+/// A built in dialect by name, or else a token table from the file `name`.
+pub fn load_dialect(name: &str) -> Result<Dialect, DialectError> {
+    match Dialect::named(name) {
+        Some(dialect) => Ok(dialect),
+        None => Dialect::load(Path::new(name)),
+    }
+}
+
+/// Interpreter settings shared by the subcommands.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// How to execute the program: tree, bytecode or jit.
+    #[clap(long, value_parser, default_value = "tree")]
+    pub engine: Engine,
+
+    /// Bits per cell: 8, 16 or 32.
+    #[clap(long, value_parser, default_value = "8")]
+    pub cell_width: CellWidth,
+
+    /// What '+' and '-' do past the cell range: wrap, saturate or error.
+    #[clap(long, value_parser, default_value = "wrap")]
+    pub overflow: Overflow,
+
+    /// What ',' stores at the end of input: zero, max or unchanged.
+    #[clap(long, value_parser, default_value = "unchanged")]
+    pub eof: EofMode,
+
+    /// How the tape behaves at its ends.
+    #[clap(long, value_enum, default_value_t = TapeKind::Fixed)]
+    pub tape: TapeKind,
+
+    /// Cells on the tape, or to start with when it grows.
+    #[clap(long, value_parser, default_value_t = TAPE_LENGTH)]
+    pub tape_length: usize,
+
+    /// Commands beyond brainfuck: brainfuck, pbrain or extended.
+    #[clap(long, value_parser, default_value = "brainfuck")]
+    pub language: Language,
+
+    /// Procedure calls a pbrain program may nest.
+    #[clap(long, value_parser, default_value_t = MAX_CALL_DEPTH)]
+    pub max_depth: usize,
+
+    /// Run the program as written, without the peephole optimizer.
+    #[clap(long, action)]
+    pub no_optimize: bool,
+}
+
+impl Options {
+    pub fn config(&self) -> Config {
+        let tape = match self.tape {
+            TapeKind::Fixed => TapeMode::Fixed(self.tape_length),
+            TapeKind::Growable => TapeMode::Growable(self.tape_length),
+            TapeKind::Bidirectional => TapeMode::Bidirectional,
+            TapeKind::Wrapping => TapeMode::Wrapping(self.tape_length),
+        };
+
+        let language = match self.language {
+            Language::Pbrain { .. } => Language::Pbrain {
+                max_depth: self.max_depth,
+            },
+            language => language,
+        };
+
+        Config {
+            cell_width: self.cell_width,
+            overflow: self.overflow,
+            tape,
+            eof: self.eof,
+            optimize: !self.no_optimize,
+            engine: self.engine,
+            language,
+        }
+    }
+}
+
+/// The kinds of [`TapeMode`], without their lengths.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapeKind {
+    Fixed,
+    Growable,
+    Bidirectional,
+    Wrapping,
+}
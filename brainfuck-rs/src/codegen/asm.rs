@@ -0,0 +1,376 @@
+use crate::{
+    codegen::tape_layout,
+    compiler::Expr,
+    config::{CellWidth, Config, EofMode, Language, Overflow},
+    error::UnsupportedError,
+};
+
+/// Size of the output buffer, flushed when full, before reading and on exit.
+const BUFFER_LENGTH: usize = 4096;
+
+/// Translate `exprs` into GNU assembler source for x86-64 Linux.
+///
+/// The result only uses raw syscalls, so it assembles and links
+/// into a static executable with `as -o bf.o bf.s && ld -o bf bf.o`.
+/// On a fixed tape the executable exits with status 1 when the pointer
+/// moves off the tape, where the interpreter would report a fault.
+pub fn emit(exprs: &[Expr], config: &Config) -> Result<String, UnsupportedError> {
+    let (length, wrapping) =
+        tape_layout("asm", exprs, config, Language::Brainfuck, &[Overflow::Wrap])?;
+
+    let mut writer = Writer {
+        config,
+        length,
+        wrapping,
+        size: match config.cell_width {
+            CellWidth::U8 => 1,
+            CellWidth::U16 => 2,
+            CellWidth::U32 => 4,
+        },
+        source: String::new(),
+        labels: 0,
+    };
+
+    // %rbx holds the tape address, %r12 the cell index
+    // and %r13 the number of buffered output bytes.
+    writer.source += "    .section .bss\n";
+    writer.source += &format!("    .lcomm tape, {}\n", length * writer.size);
+    writer.source += &format!("    .lcomm buffer, {BUFFER_LENGTH}\n");
+    writer.source += "    .lcomm byte, 1\n\n";
+    writer.source += "    .text\n";
+    writer.source += "    .globl _start\n";
+    writer.source += "_start:\n";
+    writer.line("leaq tape(%rip), %rbx");
+    writer.line("xorl %r12d, %r12d");
+    writer.line("xorl %r13d, %r13d");
+    writer.block(exprs);
+    writer.line("call flush");
+    writer.line("movl $60, %eax");
+    writer.line("xorl %edi, %edi");
+    writer.line("syscall");
+    writer.source += "\n";
+    writer.flush();
+    if !wrapping {
+        writer.fault();
+    }
+
+    Ok(writer.source)
+}
+
+struct Writer<'a> {
+    config: &'a Config,
+    length: usize,
+    wrapping: bool,
+
+    /// Bytes per cell.
+    size: usize,
+    source: String,
+    labels: usize,
+}
+
+impl Writer<'_> {
+    fn block(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let suffix = self.suffix();
+
+        match expr {
+            Expr::IncPtr(n) => self.move_by(*n as i64),
+            Expr::DecPtr(n) => self.move_by(-(*n as i64)),
+            Expr::IncData(value) => self.add(0, *value, "add"),
+            Expr::DecData(value) => self.add(0, *value, "sub"),
+            Expr::IncDataAt { offset, value } => self.add(*offset, *value, "add"),
+            Expr::DecDataAt { offset, value } => self.add(*offset, *value, "sub"),
+            Expr::Output => {
+                // Cells are little endian, the low byte comes first.
+                let done = self.label();
+                self.line(&format!("movb (%rbx,%r12,{}), %al", self.size));
+                self.line("leaq buffer(%rip), %rcx");
+                self.line("movb %al, (%rcx,%r13)");
+                self.line("incq %r13");
+                self.line(&format!("cmpq ${BUFFER_LENGTH}, %r13"));
+                self.line(&format!("jne {done}"));
+                self.line("call flush");
+                self.label_here(&done);
+            }
+            Expr::Input => {
+                let (eof, done) = (self.label(), self.label());
+                let cell = self.cell(0);
+                self.line("call flush");
+                self.line("xorl %eax, %eax");
+                self.line("xorl %edi, %edi");
+                self.line("leaq byte(%rip), %rsi");
+                self.line("movl $1, %edx");
+                self.line("syscall");
+                self.line("testq %rax, %rax");
+                self.line(&format!("jle {eof}"));
+                self.line("movzbl byte(%rip), %eax");
+                self.line(&format!("mov{suffix} {}, {cell}", self.register()));
+                self.line(&format!("jmp {done}"));
+                self.label_here(&eof);
+                match self.config.eof {
+                    EofMode::Zero => self.line(&format!("mov{suffix} $0, {cell}")),
+                    EofMode::Max => {
+                        let max = self.config.cell_width.max();
+                        self.line(&format!("mov{suffix} ${max}, {cell}"));
+                    }
+                    EofMode::Unchanged => (),
+                }
+                self.label_here(&done);
+            }
+            Expr::Loop(body) => {
+                let (start, end) = (self.label(), self.label());
+                self.line(&format!("cmp{suffix} $0, (%rbx,%r12,{})", self.size));
+                self.line(&format!("je {end}"));
+                self.label_here(&start);
+                self.block(body);
+                self.line(&format!("cmp{suffix} $0, (%rbx,%r12,{})", self.size));
+                self.line(&format!("jne {start}"));
+                self.label_here(&end);
+            }
+            Expr::Procedure(_)
+            | Expr::Call
+            | Expr::End
+            | Expr::Store
+            | Expr::Load
+            | Expr::Bitwise(_) => unreachable!("extensions are rejected up front"),
+            Expr::SetZero => self.line(&format!("mov{suffix} $0, (%rbx,%r12,{})", self.size)),
+            Expr::Scan(step) => {
+                let (start, end) = (self.label(), self.label());
+                self.label_here(&start);
+                self.line(&format!("cmp{suffix} $0, (%rbx,%r12,{})", self.size));
+                self.line(&format!("je {end}"));
+                self.move_by(*step as i64);
+                self.line(&format!("jmp {start}"));
+                self.label_here(&end);
+            }
+            Expr::MulAdd { offset, factor } => {
+                let instruction = if *factor < 0 { "sub" } else { "add" };
+                let load = match self.size {
+                    1 => "movzbl",
+                    2 => "movzwl",
+                    _ => "movl",
+                };
+
+                let done = self.label();
+                self.line(&format!("{load} (%rbx,%r12,{}), %edx", self.size));
+                self.line("testl %edx, %edx");
+                self.line(&format!("je {done}"));
+
+                // Only the low bits of the product matter when cells wrap.
+                self.line(&format!(
+                    "imull ${}, %edx, %edx",
+                    factor.unsigned_abs() as i32
+                ));
+                let cell = self.cell(*offset);
+                let register = match self.size {
+                    1 => "%dl",
+                    2 => "%dx",
+                    _ => "%edx",
+                };
+                self.line(&format!("{instruction}{suffix} {register}, {cell}"));
+                self.label_here(&done);
+            }
+        }
+    }
+
+    fn add(&mut self, offset: i32, value: u32, instruction: &str) {
+        let cell = self.cell(offset);
+        let suffix = self.suffix();
+        let value = value & self.config.cell_width.max();
+        self.line(&format!("{instruction}{suffix} ${value}, {cell}"));
+    }
+
+    fn move_by(&mut self, by: i64) {
+        if !self.wrapping {
+            self.line(&format!("addq ${by}, %r12"));
+            self.line(&format!("cmpq ${}, %r12", self.length));
+            self.line("jae fault");
+            return;
+        }
+
+        // The index stays below the length, so one subtraction brings it back.
+        let done = self.label();
+        let by = by.rem_euclid(self.length as i64);
+        self.line(&format!("addq ${by}, %r12"));
+        self.line(&format!("cmpq ${}, %r12", self.length));
+        self.line(&format!("jb {done}"));
+        self.line(&format!("subq ${}, %r12", self.length));
+        self.label_here(&done);
+    }
+
+    /// Operand for the cell `offset` cells away from the pointer,
+    /// computing its index into %rcx first.
+    fn cell(&mut self, offset: i32) -> String {
+        if offset == 0 {
+            return format!("(%rbx,%r12,{})", self.size);
+        }
+
+        if !self.wrapping {
+            self.line(&format!("leaq {offset}(%r12), %rcx"));
+            self.line(&format!("cmpq ${}, %rcx", self.length));
+            self.line("jae fault");
+            return format!("(%rbx,%rcx,{})", self.size);
+        }
+
+        let done = self.label();
+        let offset = (offset as i64).rem_euclid(self.length as i64);
+        self.line(&format!("leaq {offset}(%r12), %rcx"));
+        self.line(&format!("cmpq ${}, %rcx", self.length));
+        self.line(&format!("jb {done}"));
+        self.line(&format!("subq ${}, %rcx", self.length));
+        self.label_here(&done);
+
+        format!("(%rbx,%rcx,{})", self.size)
+    }
+
+    /// Write the buffered output, retrying until every byte is out.
+    fn flush(&mut self) {
+        self.source += "flush:\n";
+        self.line("leaq buffer(%rip), %rsi");
+        self.source += ".Lflush:\n";
+        self.line("testq %r13, %r13");
+        self.line("jle .Lflushed");
+        self.line("movl $1, %eax");
+        self.line("movl $1, %edi");
+        self.line("movq %r13, %rdx");
+        self.line("syscall");
+        self.line("testq %rax, %rax");
+        self.line("jle .Lflushed");
+        self.line("addq %rax, %rsi");
+        self.line("subq %rax, %r13");
+        self.line("jmp .Lflush");
+        self.source += ".Lflushed:\n";
+        self.line("xorl %r13d, %r13d");
+        self.line("ret");
+    }
+
+    /// Write what was output so far and exit with status 1.
+    fn fault(&mut self) {
+        self.source += "fault:\n";
+        self.line("call flush");
+        self.line("movl $60, %eax");
+        self.line("movl $1, %edi");
+        self.line("syscall");
+    }
+
+    fn suffix(&self) -> char {
+        match self.size {
+            1 => 'b',
+            2 => 'w',
+            _ => 'l',
+        }
+    }
+
+    /// The part of %eax as wide as a cell.
+    fn register(&self) -> &'static str {
+        match self.size {
+            1 => "%al",
+            2 => "%ax",
+            _ => "%eax",
+        }
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn label_here(&mut self, label: &str) {
+        self.source += label;
+        self.source += ":\n";
+    }
+
+    fn line(&mut self, line: &str) {
+        self.source += "    ";
+        self.source += line;
+        self.source += "\n";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tape::TapeMode;
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn should_match_interpreter() {
+        use std::{
+            env, fs,
+            io::Write,
+            process::{Command, Stdio},
+        };
+
+        use crate::{
+            codegen::{corpus, faults, interpret},
+            compiler::Compiler,
+            optimizer::optimize,
+        };
+
+        let dir = env::temp_dir().join(format!("brainfuck-rs-asm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (i, (config, program, input)) in corpus().into_iter().chain(faults()).enumerate() {
+            let exprs = Compiler::new(program.chars()).compile().unwrap();
+            let source = emit(&optimize(exprs, config.overflow), &config).unwrap();
+
+            let path = dir.join(format!("{i}.s"));
+            let object = dir.join(format!("{i}.o"));
+            let binary = dir.join(format!("{i}"));
+            fs::write(&path, source).unwrap();
+
+            let assembled = Command::new("as")
+                .arg("-o")
+                .arg(&object)
+                .arg(&path)
+                .status();
+            let linked = Command::new("ld")
+                .arg("-o")
+                .arg(&binary)
+                .arg(&object)
+                .status();
+            let (assembled, linked) = match (assembled, linked) {
+                (Ok(assembled), Ok(linked)) => (assembled, linked),
+                _ => {
+                    eprintln!("no assembler or linker available, skipping");
+                    return;
+                }
+            };
+            assert!(assembled.success(), "failed to assemble {}", path.display());
+            assert!(linked.success(), "failed to link {}", path.display());
+
+            let mut child = Command::new(&binary)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(input).unwrap();
+            let output = child.wait_with_output().unwrap();
+
+            let (expected, finished) = interpret(program, input, config);
+            assert_eq!(output.stdout, expected, "output differs for {program}");
+            assert_eq!(
+                output.status.success(),
+                finished,
+                "status differs for {program}"
+            );
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_reject_growing_tapes() {
+        let config = Config {
+            tape: TapeMode::Growable(16),
+            ..Default::default()
+        };
+
+        assert!(emit(&[], &config).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    codegen::tape_layout,
+    compiler::Expr,
+    config::{CellWidth, Config, EofMode, Language, Overflow},
+    error::UnsupportedError,
+};
+
+/// Translate `exprs` into a standalone C program.
+///
+/// On a fixed tape the program exits with status 1 when the pointer
+/// moves off the tape, where the interpreter would report a fault.
+pub fn emit(exprs: &[Expr], config: &Config) -> Result<String, UnsupportedError> {
+    let (length, wrapping) =
+        tape_layout("c", exprs, config, Language::Brainfuck, &[Overflow::Wrap])?;
+
+    let cell = match config.cell_width {
+        CellWidth::U8 => "uint8_t",
+        CellWidth::U16 => "uint16_t",
+        CellWidth::U32 => "uint32_t",
+    };
+
+    let mut writer = Writer {
+        config,
+        length,
+        wrapping,
+        source: String::new(),
+        depth: 1,
+    };
+
+    writer.source += "#include <stdint.h>\n";
+    writer.source += "#include <stdio.h>\n";
+    if !wrapping {
+        writer.source += "#include <stdlib.h>\n";
+    }
+    writer.source += &format!("\nstatic {cell} tape[{length}];\n\n");
+
+    if !wrapping {
+        writer.source += "static size_t checked(size_t index) {\n";
+        writer.line(&format!("if (index >= {length}) {{"));
+        writer.depth += 1;
+        writer.line("fflush(stdout);");
+        writer.line("fputs(\"pointer moved off the tape\\n\", stderr);");
+        writer.line("exit(1);");
+        writer.depth -= 1;
+        writer.line("}");
+        writer.line("return index;");
+        writer.source += "}\n\n";
+    }
+    writer.source += "int main(void) {\n";
+    writer.line("size_t p = 0;");
+    writer.block(exprs);
+    writer.line("return 0;");
+    writer.source += "}\n";
+
+    Ok(writer.source)
+}
+
+struct Writer<'a> {
+    config: &'a Config,
+    length: usize,
+    wrapping: bool,
+    source: String,
+    depth: usize,
+}
+
+impl Writer<'_> {
+    fn block(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IncPtr(n) => self.move_by(*n as i64),
+            Expr::DecPtr(n) => self.move_by(-(*n as i64)),
+            Expr::IncData(value) => self.add(0, *value, "+="),
+            Expr::DecData(value) => self.add(0, *value, "-="),
+            Expr::IncDataAt { offset, value } => self.add(*offset, *value, "+="),
+            Expr::DecDataAt { offset, value } => self.add(*offset, *value, "-="),
+            Expr::Output => self.line("putchar((unsigned char)tape[p]);"),
+            Expr::Input => {
+                self.line("{");
+                self.depth += 1;
+                self.line("int c = getchar();");
+                match self.config.eof {
+                    EofMode::Zero => self.line("tape[p] = c == EOF ? 0 : c;"),
+                    EofMode::Max => self.line("tape[p] = c == EOF ? -1 : c;"),
+                    EofMode::Unchanged => self.line("if (c != EOF) tape[p] = c;"),
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+            Expr::Loop(body) => {
+                self.line("while (tape[p]) {");
+                self.depth += 1;
+                self.block(body);
+                self.depth -= 1;
+                self.line("}");
+            }
+            Expr::Procedure(_)
+            | Expr::Call
+            | Expr::End
+            | Expr::Store
+            | Expr::Load
+            | Expr::Bitwise(_) => unreachable!("extensions are rejected up front"),
+            Expr::SetZero => self.line("tape[p] = 0;"),
+            Expr::Scan(step) => {
+                let step = self.step(*step as i64);
+                self.line(&format!("while (tape[p]) {{ {step} }}"));
+            }
+            Expr::MulAdd { offset, factor } => {
+                let cell = self.cell(*offset);
+                let operator = if *factor < 0 { "-=" } else { "+=" };
+                // Multiply as unsigned, narrow cells would otherwise promote to int.
+                self.line(&format!(
+                    "if (tape[p]) {cell} {operator} tape[p] * {}u;",
+                    factor.unsigned_abs()
+                ));
+            }
+        }
+    }
+
+    fn add(&mut self, offset: i32, value: u32, operator: &str) {
+        let cell = self.cell(offset);
+        let value = value & self.config.cell_width.max();
+        self.line(&format!("{cell} {operator} {value}u;"));
+    }
+
+    fn move_by(&mut self, by: i64) {
+        let step = self.step(by);
+        self.line(&step);
+    }
+
+    fn step(&self, by: i64) -> String {
+        if self.wrapping {
+            let by = by.rem_euclid(self.length as i64);
+            format!("p = (p + {by}) % {};", self.length)
+        } else if by < 0 {
+            format!("p = checked(p - {});", -by)
+        } else {
+            format!("p = checked(p + {by});")
+        }
+    }
+
+    /// C expression for the cell `offset` cells away from the pointer.
+    fn cell(&self, offset: i32) -> String {
+        if offset == 0 {
+            "tape[p]".to_owned()
+        } else if self.wrapping {
+            let offset = (offset as i64).rem_euclid(self.length as i64);
+            format!("tape[(p + {offset}) % {}]", self.length)
+        } else if offset < 0 {
+            format!("tape[checked(p - {})]", -offset)
+        } else {
+            format!("tape[checked(p + {offset})]")
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        self.source += &"    ".repeat(self.depth);
+        self.source += line;
+        self.source += "\n";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    use super::*;
+    use crate::{
+        codegen::{corpus, faults, interpret},
+        compiler::Compiler,
+        optimizer::optimize,
+        tape::TapeMode,
+    };
+
+    #[test]
+    fn should_match_interpreter() {
+        let dir = env::temp_dir().join(format!("brainfuck-rs-c-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (i, (config, program, input)) in corpus().into_iter().chain(faults()).enumerate() {
+            let exprs = Compiler::new(program.chars()).compile().unwrap();
+            let source = emit(&optimize(exprs, config.overflow), &config).unwrap();
+
+            let path = dir.join(format!("{i}.c"));
+            let binary = dir.join(format!("{i}"));
+            fs::write(&path, source).unwrap();
+
+            let status = match Command::new("cc")
+                .arg("-o")
+                .arg(&binary)
+                .arg(&path)
+                .status()
+            {
+                Ok(status) => status,
+                Err(_) => {
+                    eprintln!("no C compiler available, skipping");
+                    return;
+                }
+            };
+            assert!(status.success(), "failed to compile {}", path.display());
+
+            let mut child = Command::new(&binary)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(input).unwrap();
+            let output = child.wait_with_output().unwrap();
+
+            let (expected, finished) = interpret(program, input, config);
+            assert_eq!(output.stdout, expected, "output differs for {program}");
+            assert_eq!(
+                output.status.success(),
+                finished,
+                "status differs for {program}"
+            );
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_reject_growing_tapes() {
+        let config = Config {
+            tape: TapeMode::Bidirectional,
+            ..Default::default()
+        };
+
+        assert!(emit(&[], &config).is_err());
+    }
+}
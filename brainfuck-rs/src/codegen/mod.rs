@@ -0,0 +1,178 @@
+pub mod asm;
+pub mod c;
+pub mod rust;
+pub mod wat;
+
+use std::str::FromStr;
+
+use crate::{
+    compiler::{extension, Expr},
+    config::{Config, Language, Overflow},
+    error::UnsupportedError,
+    tape::TapeMode,
+};
+
+/// Language a program can be translated into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Asm,
+    C,
+    Rust,
+    Wat,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asm" => Ok(Target::Asm),
+            "c" => Ok(Target::C),
+            "rust" => Ok(Target::Rust),
+            "wat" => Ok(Target::Wat),
+            _ => Err(format!("unknown target '{s}'")),
+        }
+    }
+}
+
+/// Translate `exprs` into a standalone program in the `target` language,
+/// behaving like the interpreter would with `config`.
+pub fn emit(target: Target, exprs: &[Expr], config: &Config) -> Result<String, UnsupportedError> {
+    match target {
+        Target::Asm => asm::emit(exprs, config),
+        Target::C => c::emit(exprs, config),
+        Target::Rust => rust::emit(exprs, config),
+        Target::Wat => wat::emit(exprs, config),
+    }
+}
+
+/// Length of the tape `backend` sets aside for `config` and whether it wraps,
+/// after checking it handles `exprs` with the commands of `language` and cells
+/// that overflow one of the `overflows` ways.
+///
+/// On a fixed tape the backends check every index as an unsigned number. Below
+/// zero wraps around to a huge one, so one comparison with the length catches both ends.
+pub(crate) fn tape_layout(
+    backend: &'static str,
+    exprs: &[Expr],
+    config: &Config,
+    language: Language,
+    overflows: &[Overflow],
+) -> Result<(usize, bool), UnsupportedError> {
+    let unsupported = |feature| UnsupportedError { backend, feature };
+
+    if let Some(feature) = extension(exprs, language) {
+        return Err(unsupported(feature));
+    }
+
+    if !overflows.contains(&config.overflow) {
+        return Err(unsupported(match config.overflow {
+            Overflow::Wrap => "wrapping cells",
+            Overflow::Saturate => "saturating cells",
+            Overflow::Error => "checked cells",
+        }));
+    }
+
+    match config.tape {
+        TapeMode::Fixed(length) => Ok((length.max(1), false)),
+        TapeMode::Wrapping(length) => Ok((length.max(1), true)),
+        TapeMode::Growable(_) | TapeMode::Bidirectional => Err(unsupported("growing tapes")),
+    }
+}
+
+/// Programs every backend should run exactly like the interpreter.
+#[cfg(test)]
+pub(crate) fn corpus() -> Vec<(Config, &'static str, &'static [u8])> {
+    use crate::config::{CellWidth, EofMode};
+
+    let config = Config {
+        optimize: true,
+        ..Default::default()
+    };
+
+    vec![
+        (config, include_str!("../../scripts/hello_world.bf"), b""),
+        (config, include_str!("../../scripts/rot13.bf"), b"Hello, World!\n"),
+        (
+            Config {
+                eof: EofMode::Zero,
+                ..config
+            },
+            ",[.,]",
+            b"line one\nline two\n",
+        ),
+        (
+            Config {
+                eof: EofMode::Max,
+                ..config
+            },
+            ",+[-.,+]",
+            b"abc\n",
+        ),
+        (config, "-.>+++[->+++++>-<<]>>.<.", b""),
+        (
+            Config {
+                cell_width: CellWidth::U16,
+                ..config
+            },
+            "++++++++++++++++[>++++++++++++++++<-]>[>+++++++++++[<+++++++++++>-]<.[-]]-[>+<-]>[[-]+++++++++++++++++++++++++++++++++.[-]]",
+            b"",
+        ),
+        (
+            Config {
+                cell_width: CellWidth::U32,
+                ..config
+            },
+            "-[>+<-]>[[-]+++++++++++++++++++++++++++++++++.[-]]",
+            b"",
+        ),
+        (
+            Config {
+                tape: TapeMode::Wrapping(10),
+                ..config
+            },
+            "<<+++++[>++++++++++<-]>>>>>>>>>>>>.<[<]>.",
+            b"",
+        ),
+        // The loop never runs, so it never reaches past the end of the tape.
+        (
+            Config {
+                tape: TapeMode::Fixed(4),
+                ..config
+            },
+            "+.>[->>>>+<<<<]<.",
+            b"",
+        ),
+    ]
+}
+
+/// Programs that move off a fixed tape, which every backend should stop
+/// with an error after printing what the interpreter printed before the fault.
+#[cfg(test)]
+pub(crate) fn faults() -> Vec<(Config, &'static str, &'static [u8])> {
+    let config = Config {
+        optimize: true,
+        tape: TapeMode::Fixed(4),
+        ..Default::default()
+    };
+
+    vec![
+        (config, "+.<.", b""),
+        (config, "+.>>>>.", b""),
+        (config, "+[>+]", b""),
+        (config, "+.>+<[->>>>+<<<<]", b""),
+        // Turning back without touching the last cell still leaves the tape.
+        (config, "+.>>>><<<<.", b""),
+        (config, "+.[->>>><+<<<]", b""),
+    ]
+}
+
+/// What the interpreter prints for `program`, and whether it ran without a fault.
+#[cfg(test)]
+pub(crate) fn interpret(program: &str, input: &[u8], config: Config) -> (Vec<u8>, bool) {
+    let mut brainfuck = crate::BrainFuck::with_config(config, input, vec![]);
+    let finished = brainfuck.compile(program).is_ok();
+
+    let (_, output) = brainfuck.into_io();
+    (output, finished)
+}
@@ -0,0 +1,307 @@
+use crate::{
+    codegen::tape_layout,
+    compiler::Expr,
+    config::{CellWidth, Config, EofMode, Language, Overflow},
+    error::UnsupportedError,
+};
+
+/// Translate `exprs` into a Rust function `run(input: &[u8]) -> Vec<u8>`.
+///
+/// On a fixed tape `run` panics when the pointer moves off the tape,
+/// where the interpreter would report a fault.
+pub fn emit(exprs: &[Expr], config: &Config) -> Result<String, UnsupportedError> {
+    let (length, wrapping) = tape_layout(
+        "rust",
+        exprs,
+        config,
+        Language::Brainfuck,
+        &[Overflow::Wrap, Overflow::Saturate],
+    )?;
+    let saturate = config.overflow == Overflow::Saturate;
+
+    let cell = match config.cell_width {
+        CellWidth::U8 => "u8",
+        CellWidth::U16 => "u16",
+        CellWidth::U32 => "u32",
+    };
+
+    let mut writer = Writer {
+        config,
+        length,
+        wrapping,
+        saturate,
+        source: String::new(),
+        depth: 1,
+    };
+
+    writer.source += "#[allow(unused)]\n";
+    writer.source += "pub fn run(input: &[u8]) -> Vec<u8> {\n";
+    writer.line(&format!("let mut tape = vec![0{cell}; {length}];"));
+    writer.line("let mut p = 0usize;");
+    writer.line("let mut input = input.iter();");
+    writer.line("let mut output = Vec::new();");
+    if !wrapping {
+        writer.line(&format!(
+            "let checked = |index: usize| {{ assert!(index < {length}, \"pointer moved off the tape\"); index }};"
+        ));
+    }
+    writer.block(exprs);
+    writer.line("output");
+    writer.source += "}\n";
+
+    Ok(writer.source)
+}
+
+struct Writer<'a> {
+    config: &'a Config,
+    length: usize,
+    wrapping: bool,
+    saturate: bool,
+    source: String,
+    depth: usize,
+}
+
+impl Writer<'_> {
+    fn block(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IncPtr(n) => self.move_by(*n as i64),
+            Expr::DecPtr(n) => self.move_by(-(*n as i64)),
+            Expr::IncData(value) => self.add(0, *value, "add"),
+            Expr::DecData(value) => self.add(0, *value, "sub"),
+            Expr::IncDataAt { offset, value } => self.add(*offset, *value, "add"),
+            Expr::DecDataAt { offset, value } => self.add(*offset, *value, "sub"),
+            Expr::Output => self.line("output.push(tape[p] as u8);"),
+            Expr::Input => {
+                let eof = match self.config.eof {
+                    EofMode::Zero => "tape[p] = 0",
+                    EofMode::Max => "tape[p] = !0",
+                    EofMode::Unchanged => "()",
+                };
+                self.line(&format!(
+                    "match input.next() {{ Some(byte) => tape[p] = *byte as _, None => {eof} }}"
+                ));
+            }
+            Expr::Loop(body) => {
+                self.line("while tape[p] != 0 {");
+                self.depth += 1;
+                self.block(body);
+                self.depth -= 1;
+                self.line("}");
+            }
+            Expr::Procedure(_)
+            | Expr::Call
+            | Expr::End
+            | Expr::Store
+            | Expr::Load
+            | Expr::Bitwise(_) => unreachable!("extensions are rejected up front"),
+            Expr::SetZero => self.line("tape[p] = 0;"),
+            Expr::Scan(step) => {
+                let step = self.step(*step as i64);
+                self.line(&format!("while tape[p] != 0 {{ {step} }}"));
+            }
+            Expr::MulAdd { offset, factor } => {
+                let cell = self.cell(*offset);
+                let method = if *factor < 0 { "sub" } else { "add" };
+                let kind = self.kind();
+                let factor = self.constant(factor.unsigned_abs());
+
+                self.line(&format!(
+                    "if tape[p] != 0 {{ {cell} = {cell}.{kind}_{method}(tape[p].{kind}_mul({factor})); }}"
+                ));
+            }
+        }
+    }
+
+    fn add(&mut self, offset: i32, value: u32, method: &str) {
+        let cell = self.cell(offset);
+        let kind = self.kind();
+        let value = self.constant(value);
+        self.line(&format!("{cell} = {cell}.{kind}_{method}({value});"));
+    }
+
+    /// Fit `value` into a cell literal.
+    fn constant(&self, value: u32) -> u32 {
+        let max = self.config.cell_width.max();
+
+        // Saturating by more than the range is the same as by all of it.
+        if self.saturate {
+            value.min(max)
+        } else {
+            value & max
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.saturate {
+            "saturating"
+        } else {
+            "wrapping"
+        }
+    }
+
+    fn move_by(&mut self, by: i64) {
+        let step = self.step(by);
+        self.line(&step);
+    }
+
+    fn step(&self, by: i64) -> String {
+        if self.wrapping {
+            let by = by.rem_euclid(self.length as i64);
+            format!("p = (p + {by}) % {};", self.length)
+        } else if by < 0 {
+            format!("p = checked(p.wrapping_sub({}));", -by)
+        } else {
+            format!("p = checked(p + {by});")
+        }
+    }
+
+    /// Rust expression for the cell `offset` cells away from the pointer.
+    fn cell(&self, offset: i32) -> String {
+        if offset == 0 {
+            "tape[p]".to_owned()
+        } else if self.wrapping {
+            let offset = (offset as i64).rem_euclid(self.length as i64);
+            format!("tape[(p + {offset}) % {}]", self.length)
+        } else if offset < 0 {
+            format!("tape[checked(p.wrapping_sub({}))]", -offset)
+        } else {
+            format!("tape[checked(p + {offset})]")
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        self.source += &"    ".repeat(self.depth);
+        self.source += line;
+        self.source += "\n";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    use super::*;
+    use crate::{
+        codegen::{corpus, faults, interpret},
+        compiler::Compiler,
+        optimizer::optimize,
+    };
+
+    #[test]
+    fn should_emit_run_function() {
+        let exprs = Compiler::new(",[.,]".chars()).compile().unwrap();
+        let config = Config {
+            eof: EofMode::Zero,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            emit(&exprs, &config).unwrap(),
+            "#[allow(unused)]
+pub fn run(input: &[u8]) -> Vec<u8> {
+    let mut tape = vec![0u8; 30000];
+    let mut p = 0usize;
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    let checked = |index: usize| { assert!(index < 30000, \"pointer moved off the tape\"); index };
+    match input.next() { Some(byte) => tape[p] = *byte as _, None => tape[p] = 0 }
+    while tape[p] != 0 {
+        output.push(tape[p] as u8);
+        match input.next() { Some(byte) => tape[p] = *byte as _, None => tape[p] = 0 }
+    }
+    output
+}
+"
+        );
+    }
+
+    #[test]
+    fn should_match_interpreter() {
+        let cases = corpus().into_iter().chain(faults()).collect::<Vec<_>>();
+
+        // One crate with a module per program, picked by the first argument.
+        let mut source = String::new();
+        for (i, (config, program, _)) in cases.iter().enumerate() {
+            let exprs = Compiler::new(program.chars()).compile().unwrap();
+            let module = emit(&optimize(exprs, config.overflow), config).unwrap();
+            source += &format!("mod program{i} {{\n{module}}}\n\n");
+        }
+        source += "fn main() {\n";
+        source += "    use std::io::{Read, Write};\n";
+        source += "    let mut input = Vec::new();\n";
+        source += "    std::io::stdin().read_to_end(&mut input).unwrap();\n";
+        source += "    let output = match std::env::args().nth(1).unwrap().as_str() {\n";
+        for i in 0..cases.len() {
+            source += &format!("        \"{i}\" => program{i}::run(&input),\n");
+        }
+        source += "        _ => unreachable!(),\n";
+        source += "    };\n";
+        source += "    std::io::stdout().write_all(&output).unwrap();\n";
+        source += "}\n";
+
+        let dir = env::temp_dir().join(format!("brainfuck-rs-rust-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("programs.rs");
+        let binary = dir.join("programs");
+        fs::write(&path, source).unwrap();
+
+        let status = match Command::new("rustc")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&path)
+            .status()
+        {
+            Ok(status) => status,
+            Err(_) => {
+                eprintln!("no Rust compiler available, skipping");
+                return;
+            }
+        };
+        assert!(status.success(), "failed to compile {}", path.display());
+
+        for (i, (config, program, input)) in cases.into_iter().enumerate() {
+            let mut child = Command::new(&binary)
+                .arg(i.to_string())
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(input).unwrap();
+            let output = child.wait_with_output().unwrap();
+
+            // `run` only hands its output back when it returns, so a panic loses it.
+            let (expected, finished) = interpret(program, input, config);
+            assert_eq!(
+                output.status.success(),
+                finished,
+                "status differs for {program}"
+            );
+            if finished {
+                assert_eq!(output.stdout, expected, "output differs for {program}");
+            }
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_reject_checked_cells() {
+        let config = Config {
+            overflow: Overflow::Error,
+            ..Default::default()
+        };
+
+        assert!(emit(&[], &config).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{
+    codegen::tape_layout,
+    compiler::Expr,
+    config::{CellWidth, Config, EofMode, Language, Overflow},
+    error::UnsupportedError,
+};
+
+const PAGE_SIZE: usize = 65536;
+
+/// Translate `exprs` into a WebAssembly text module.
+///
+/// The module exports its `memory`, holding the tape, and a `run` function.
+/// It imports `env.read_byte`, returning the next input byte or -1 at the
+/// end of input, and `env.write_byte`, taking the byte to output.
+/// On a fixed tape `run` traps when the pointer moves off the tape,
+/// where the interpreter would report a fault.
+pub fn emit(exprs: &[Expr], config: &Config) -> Result<String, UnsupportedError> {
+    let (length, wrapping) =
+        tape_layout("wat", exprs, config, Language::Brainfuck, &[Overflow::Wrap])?;
+
+    let size = match config.cell_width {
+        CellWidth::U8 => 1,
+        CellWidth::U16 => 2,
+        CellWidth::U32 => 4,
+    };
+
+    let mut writer = Writer {
+        config,
+        // The pointer is kept as a byte address, so are these.
+        length: length * size,
+        wrapping,
+        size,
+        source: String::new(),
+        depth: 1,
+    };
+
+    writer.source += "(module\n";
+    writer.line("(import \"env\" \"read_byte\" (func $read_byte (result i32)))");
+    writer.line("(import \"env\" \"write_byte\" (func $write_byte (param i32)))");
+    writer.line(&format!(
+        "(memory (export \"memory\") {})",
+        writer.length.div_ceil(PAGE_SIZE)
+    ));
+    if !wrapping {
+        writer.line("(func $checked (param $address i32) (result i32)");
+        writer.depth += 1;
+        writer.line(&format!(
+            "(if (i32.ge_u (local.get $address) (i32.const {})) (then unreachable))",
+            writer.length
+        ));
+        writer.line("(local.get $address)");
+        writer.depth -= 1;
+        writer.line(")");
+    }
+    writer.line("(func (export \"run\")");
+    writer.depth += 1;
+    writer.line("(local $p i32)");
+    writer.line("(local $byte i32)");
+    writer.block(exprs);
+    writer.depth -= 1;
+    writer.line(")");
+    writer.source += ")\n";
+
+    Ok(writer.source)
+}
+
+struct Writer<'a> {
+    config: &'a Config,
+    length: usize,
+    wrapping: bool,
+
+    /// Bytes per cell.
+    size: usize,
+    source: String,
+    depth: usize,
+}
+
+impl Writer<'_> {
+    fn block(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IncPtr(n) => self.move_by(*n as i64),
+            Expr::DecPtr(n) => self.move_by(-(*n as i64)),
+            Expr::IncData(value) => self.add(0, *value, "add"),
+            Expr::DecData(value) => self.add(0, *value, "sub"),
+            Expr::IncDataAt { offset, value } => self.add(*offset, *value, "add"),
+            Expr::DecDataAt { offset, value } => self.add(*offset, *value, "sub"),
+            Expr::Output => {
+                let cell = self.load(0);
+                self.line(&format!(
+                    "(call $write_byte (i32.and {cell} (i32.const 255)))"
+                ));
+            }
+            Expr::Input => {
+                self.line("(local.set $byte (call $read_byte))");
+                self.line("(if (i32.ge_s (local.get $byte) (i32.const 0))");
+                self.depth += 1;
+                let store = self.store(0, "(local.get $byte)");
+                self.line(&format!("(then {store})"));
+
+                let eof = match self.config.eof {
+                    EofMode::Zero => Some(0),
+                    EofMode::Max => Some(self.config.cell_width.max()),
+                    EofMode::Unchanged => None,
+                };
+                if let Some(value) = eof {
+                    let store = self.store(0, &format!("(i32.const {})", value as i32));
+                    self.line(&format!("(else {store})"));
+                }
+
+                self.depth -= 1;
+                self.line(")");
+            }
+            Expr::Loop(body) => {
+                self.open_loop();
+                self.block(body);
+                self.close_loop();
+            }
+            Expr::Procedure(_)
+            | Expr::Call
+            | Expr::End
+            | Expr::Store
+            | Expr::Load
+            | Expr::Bitwise(_) => unreachable!("extensions are rejected up front"),
+            Expr::SetZero => {
+                let store = self.store(0, "(i32.const 0)");
+                self.line(&store);
+            }
+            Expr::Scan(step) => {
+                self.open_loop();
+                self.move_by(*step as i64);
+                self.close_loop();
+            }
+            Expr::MulAdd { offset, factor } => {
+                // Wrapping multiplication by the signed factor adds up
+                // to the same low bits as repeated subtraction would.
+                let product = format!("(i32.mul {} (i32.const {factor}))", self.load(0));
+                let sum = format!("(i32.add {} {product})", self.load(*offset));
+                let store = self.store(*offset, &sum);
+
+                self.line(&format!("(if {} (then {store}))", self.load(0)));
+            }
+        }
+    }
+
+    /// Start a loop running while the current cell is not zero.
+    fn open_loop(&mut self) {
+        let cell = self.load(0);
+        self.line("(block");
+        self.depth += 1;
+        self.line("(loop");
+        self.depth += 1;
+        self.line(&format!("(br_if 1 (i32.eqz {cell}))"));
+    }
+
+    fn close_loop(&mut self) {
+        self.line("(br 0)");
+        self.depth -= 1;
+        self.line(")");
+        self.depth -= 1;
+        self.line(")");
+    }
+
+    fn add(&mut self, offset: i32, value: u32, instruction: &str) {
+        let value = value & self.config.cell_width.max();
+        let sum = format!(
+            "(i32.{instruction} {} (i32.const {}))",
+            self.load(offset),
+            value as i32
+        );
+        let store = self.store(offset, &sum);
+        self.line(&store);
+    }
+
+    fn move_by(&mut self, by: i64) {
+        let by = by * self.size as i64;
+
+        if self.wrapping {
+            let by = by.rem_euclid(self.length as i64);
+            self.line(&format!(
+                "(local.set $p (i32.rem_u (i32.add (local.get $p) (i32.const {by})) (i32.const {})))",
+                self.length
+            ));
+        } else {
+            self.line(&format!(
+                "(local.set $p (call $checked (i32.add (local.get $p) (i32.const {by}))))"
+            ));
+        }
+    }
+
+    fn load(&self, offset: i32) -> String {
+        let instruction = match self.size {
+            1 => "i32.load8_u",
+            2 => "i32.load16_u",
+            _ => "i32.load",
+        };
+
+        format!("({instruction} {})", self.address(offset))
+    }
+
+    fn store(&self, offset: i32, value: &str) -> String {
+        let instruction = match self.size {
+            1 => "i32.store8",
+            2 => "i32.store16",
+            _ => "i32.store",
+        };
+
+        format!("({instruction} {} {value})", self.address(offset))
+    }
+
+    /// Byte address of the cell `offset` cells away from the pointer.
+    fn address(&self, offset: i32) -> String {
+        let offset = offset as i64 * self.size as i64;
+
+        if offset == 0 {
+            "(local.get $p)".to_owned()
+        } else if self.wrapping {
+            let offset = offset.rem_euclid(self.length as i64);
+            format!(
+                "(i32.rem_u (i32.add (local.get $p) (i32.const {offset})) (i32.const {}))",
+                self.length
+            )
+        } else {
+            format!("(call $checked (i32.add (local.get $p) (i32.const {offset})))")
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        self.source += &"  ".repeat(self.depth);
+        self.source += line;
+        self.source += "\n";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    use super::*;
+    use crate::{
+        codegen::{corpus, faults, interpret},
+        compiler::Compiler,
+        optimizer::optimize,
+    };
+
+    struct Host<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    /// What the module outputs, and whether `run` returned without a trap.
+    fn run_module(source: &str, input: &[u8]) -> (Vec<u8>, bool) {
+        let wasm = wat::parse_str(source).unwrap();
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wasm).unwrap();
+
+        let mut store = Store::new(
+            &engine,
+            Host {
+                input,
+                output: vec![],
+            },
+        );
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap("env", "read_byte", |mut caller: Caller<Host>| {
+                let host = caller.data_mut();
+                match host.input.split_first() {
+                    Some((byte, rest)) => {
+                        host.input = rest;
+                        *byte as i32
+                    }
+                    None => -1,
+                }
+            })
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "write_byte",
+                |mut caller: Caller<Host>, byte: i32| caller.data_mut().output.push(byte as u8),
+            )
+            .unwrap();
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let run = instance.get_typed_func::<(), ()>(&store, "run").unwrap();
+        let finished = run.call(&mut store, ()).is_ok();
+
+        (store.into_data().output, finished)
+    }
+
+    #[test]
+    fn should_match_interpreter() {
+        for (config, program, input) in corpus().into_iter().chain(faults()) {
+            let exprs = Compiler::new(program.chars()).compile().unwrap();
+            let source = emit(&optimize(exprs, config.overflow), &config).unwrap();
+
+            let expected = interpret(program, input, config);
+            assert_eq!(
+                run_module(&source, input),
+                expected,
+                "output differs for {program}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_saturating_cells() {
+        let config = Config {
+            overflow: Overflow::Saturate,
+            ..Default::default()
+        };
+
+        assert!(emit(&[], &config).is_err());
+    }
+}
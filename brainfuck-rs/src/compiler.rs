@@ -1,62 +1,225 @@
-use std::{iter::Peekable, str::Chars};
+use std::{fmt::Display, iter::Peekable, str::CharIndices, str::Chars};
 
-#[derive(PartialEq, Eq, Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Language,
+    error::{ParseError, UnmatchedBracket},
+};
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Expr {
     IncPtr(u32),
     DecPtr(u32),
-    IncData(u8),
-    DecData(u8),
+    IncData(u32),
+    DecData(u32),
     Input,
     Output,
     Loop(Vec<Expr>),
+
+    /// Add to the cell `offset` cells away without moving the pointer.
+    IncDataAt {
+        offset: i32,
+        value: u32,
+    },
+
+    /// Subtract from the cell `offset` cells away without moving the pointer.
+    DecDataAt {
+        offset: i32,
+        value: u32,
+    },
+
+    /// Set the current cell to zero, `[-]`.
+    SetZero,
+
+    /// Move the pointer by the step until it reaches a zero cell, `[>]`.
+    Scan(i32),
+
+    /// Add `factor` times the current cell to the cell `offset` cells away.
+    /// Always followed by [`Expr::SetZero`], together forming a loop like `[->++<]`.
+    ///
+    /// Like that loop, it leaves the other cell alone when the current cell is zero,
+    /// which matters when the other cell is off a fixed tape.
+    MulAdd {
+        offset: i32,
+        factor: i32,
+    },
+
+    /// Define the body as the procedure numbered by the current cell, pbrain `(...)`.
+    Procedure(Vec<Expr>),
+
+    /// Run the procedure numbered by the current cell, pbrain `:`.
+    Call,
+
+    /// Stop the program, Extended Type I `@`.
+    End,
+
+    /// Copy the current cell into the storage register, `$`.
+    Store,
+
+    /// Copy the storage register into the current cell, `!`.
+    Load,
+
+    /// Replace the current cell by a bitwise operation on it, `}{~^&|`.
+    Bitwise(BitOp),
+}
+
+/// The bitwise commands of Extended Type I.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BitOp {
+    /// Shift right by one bit, `}`.
+    ShiftRight,
+
+    /// Shift left by one bit, dropping the bit that leaves the cell, `{`.
+    ShiftLeft,
+
+    /// Flip every bit of the cell, `~`.
+    Not,
+
+    /// Exclusive or with the storage register, `^`.
+    Xor,
+
+    /// And with the storage register, `&`.
+    And,
+
+    /// Or with the storage register, `|`.
+    Or,
+}
+
+impl BitOp {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '}' => Some(BitOp::ShiftRight),
+            '{' => Some(BitOp::ShiftLeft),
+            '~' => Some(BitOp::Not),
+            '^' => Some(BitOp::Xor),
+            '&' => Some(BitOp::And),
+            '|' => Some(BitOp::Or),
+            _ => None,
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match self {
+            BitOp::ShiftRight => '}',
+            BitOp::ShiftLeft => '{',
+            BitOp::Not => '~',
+            BitOp::Xor => '^',
+            BitOp::And => '&',
+            BitOp::Or => '|',
+        }
+    }
+
+    /// The new value of a cell holding `cell`, on a tape whose largest value is `max`.
+    pub fn apply(&self, cell: u32, storage: u32, max: u32) -> u32 {
+        match self {
+            BitOp::ShiftRight => cell >> 1,
+            BitOp::ShiftLeft => (cell << 1) & max,
+            BitOp::Not => !cell & max,
+            BitOp::Xor => cell ^ storage,
+            BitOp::And => cell & storage,
+            BitOp::Or => cell | storage,
+        }
+    }
+}
+
+/// Byte range of the source an expression was compiled from.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
 pub struct Compiler<'a> {
-    chars: Peekable<Chars<'a>>,
+    source: &'a str,
+    language: Language,
+    chars: Peekable<CharIndices<'a>>,
+    spans: Vec<Span>,
 }
 
 impl<'a> Compiler<'a> {
     pub fn new(chars: Chars<'a>) -> Self {
+        Self::with_language(chars, Language::Brainfuck)
+    }
+
+    /// Compile with the extra commands of `language`.
+    pub fn with_language(chars: Chars<'a>, language: Language) -> Self {
+        let source = chars.as_str();
+
         Self {
-            chars: chars.peekable(),
+            source,
+            language,
+            chars: source.char_indices().peekable(),
+            spans: vec![],
         }
     }
 
-    pub fn compile(&mut self) -> Vec<Expr> {
-        let mut tokens = vec![];
+    pub fn compile(&mut self) -> Result<Vec<Expr>, ParseError> {
+        validate_with_language(self.source, self.language)?;
 
         self.skip_loop();
 
-        while let Some(c) = self.chars.next() {
-            self.compile_token(&mut tokens, c);
+        Ok(self.compile_block())
+    }
+
+    /// Compile a program that continues on a tape already in use,
+    /// keeping a leading loop since the current cell may not be zero.
+    pub fn compile_continuation(&mut self) -> Result<Vec<Expr>, ParseError> {
+        validate_with_language(self.source, self.language)?;
+
+        Ok(self.compile_block())
+    }
+
+    /// Source spans of the compiled expressions, in the order
+    /// a depth-first walk of the tree visits them.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Compile tokens until the end of the program or the `]` or `)`
+    /// closing the loop or procedure currently being compiled.
+    fn compile_block(&mut self) -> Vec<Expr> {
+        let mut tokens = vec![];
+
+        while let Some((offset, c)) = self.chars.next() {
+            if c == ']' || c == ')' && self.language.has_procedures() {
+                break;
+            }
+
+            self.compile_token(&mut tokens, offset, c);
         }
 
         tokens
     }
 
     fn skip_loop(&mut self) {
-        if let Some('[') = self.chars.peek() {
+        if let Some((_, '[')) = self.chars.peek() {
             self.chars.next();
             let mut depth = 1usize;
 
             while depth > 0 {
                 match self.chars.next() {
-                    Some('[') => depth += 1,
-                    Some(']') => {
-                        // FIXME: Prevent underflow;
-                        depth -= 1;
-                    }
-                    _ => (),
+                    Some((_, '[')) => depth += 1,
+                    Some((_, ']')) => depth -= 1,
+                    Some(_) => (),
+                    None => break,
                 }
             }
         }
     }
 
-    fn compile_token(&mut self, tokens: &mut Vec<Expr>, c: char) {
+    fn offset(&mut self) -> usize {
+        match self.chars.peek() {
+            Some((offset, _)) => *offset,
+            None => self.source.len(),
+        }
+    }
+
+    fn compile_token(&mut self, tokens: &mut Vec<Expr>, offset: usize, c: char) {
         macro_rules! count_chars {
             ($c:expr) => {{
                 let mut value = 1;
-                while matches!(self.chars.peek(), Some($c)) {
+                while matches!(self.chars.peek(), Some((_, $c))) {
                     value += 1;
                     self.chars.next();
                 }
@@ -64,43 +227,237 @@ impl<'a> Compiler<'a> {
             }};
         }
 
+        // Reserve the span before compiling, so a loop comes before its body.
+        let index = self.spans.len();
+        self.spans.push(Span {
+            start: offset,
+            end: offset,
+        });
+
         let token = match c {
             '>' => Expr::IncPtr(count_chars!('>')),
             '<' => Expr::DecPtr(count_chars!('<')),
-            '+' => Expr::IncData((count_chars!('+') % 256) as u8),
-            '-' => Expr::DecData((count_chars!('-') % 256) as u8),
+            '+' => Expr::IncData(count_chars!('+')),
+            '-' => Expr::DecData(count_chars!('-')),
             '.' => Expr::Output,
             ',' => Expr::Input,
-            '[' => {
-                let mut loop_tokens = vec![];
-                loop {
-                    match self.chars.peek() {
-                        Some('[') | None => break,
-                        Some(_) => {
-                            // call next and unwrap peeked value to avoid copying.
-                            let c = self.chars.next().unwrap();
-                            self.compile_token(&mut loop_tokens, c);
-                        }
-                    }
+            '[' => Expr::Loop(self.compile_block()),
+            '(' if self.language.has_procedures() => Expr::Procedure(self.compile_block()),
+            ':' if self.language.has_procedures() => Expr::Call,
+            '@' if self.language.is_extended() => Expr::End,
+            '$' if self.language.is_extended() => Expr::Store,
+            '!' if self.language.is_extended() => Expr::Load,
+            _ => match BitOp::from_char(c) {
+                Some(op) if self.language.is_extended() => Expr::Bitwise(op),
+                _ => {
+                    self.spans.pop();
+                    return;
                 }
-
-                Expr::Loop(loop_tokens)
-            }
-            _ => return,
+            },
         };
 
+        self.spans[index].end = self.offset();
+
         tokens.push(token);
     }
 }
 
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::IncPtr(n) => write!(f, "{}", ">".repeat(*n as usize)),
+            Expr::DecPtr(n) => write!(f, "{}", "<".repeat(*n as usize)),
+            Expr::IncData(n) => write!(f, "{}", "+".repeat(*n as usize)),
+            Expr::DecData(n) => write!(f, "{}", "-".repeat(*n as usize)),
+            Expr::Input => write!(f, ","),
+            Expr::Output => write!(f, "."),
+            Expr::Loop(body) => write!(f, "[{}]", to_source(body)),
+            Expr::Procedure(body) => write!(f, "({})", to_source(body)),
+            Expr::Call => write!(f, ":"),
+            Expr::End => write!(f, "@"),
+            Expr::Store => write!(f, "$"),
+            Expr::Load => write!(f, "!"),
+            Expr::Bitwise(op) => write!(f, "{}", op.as_char()),
+            Expr::IncDataAt { offset, value } => {
+                write!(
+                    f,
+                    "{}{}{}",
+                    moves(*offset),
+                    "+".repeat(*value as usize),
+                    moves(-offset)
+                )
+            }
+            Expr::DecDataAt { offset, value } => {
+                write!(
+                    f,
+                    "{}{}{}",
+                    moves(*offset),
+                    "-".repeat(*value as usize),
+                    moves(-offset)
+                )
+            }
+            Expr::SetZero => write!(f, "[-]"),
+            Expr::Scan(step) => write!(f, "[{}]", moves(*step)),
+            Expr::MulAdd { .. } => write!(f, "[-{}]", mul_add_body(std::slice::from_ref(self))),
+        }
+    }
+}
+
+fn moves(offset: i32) -> String {
+    if offset < 0 {
+        "<".repeat(offset.unsigned_abs() as usize)
+    } else {
+        ">".repeat(offset as usize)
+    }
+}
+
+fn mul_add_body(exprs: &[Expr]) -> String {
+    let mut source = String::new();
+    let mut position = 0;
+
+    for expr in exprs {
+        if let Expr::MulAdd { offset, factor } = expr {
+            let sign = if *factor < 0 { "-" } else { "+" };
+            source += &moves(offset - position);
+            source += &sign.repeat(factor.unsigned_abs() as usize);
+            position = *offset;
+        }
+    }
+
+    source + &moves(-position)
+}
+
+/// Number of expressions in `exprs`, counting loop bodies.
+pub fn node_count(exprs: &[Expr]) -> usize {
+    exprs
+        .iter()
+        .map(|expr| match expr {
+            Expr::Loop(body) | Expr::Procedure(body) => 1 + node_count(body),
+            _ => 1,
+        })
+        .sum()
+}
+
+/// The first feature `exprs` use beyond what `language` has, if any,
+/// for backends that only translate some of the languages.
+pub fn extension(exprs: &[Expr], language: Language) -> Option<&'static str> {
+    exprs.iter().find_map(|expr| match expr {
+        Expr::Procedure(_) | Expr::Call if !language.has_procedures() => Some("pbrain procedures"),
+        Expr::End | Expr::Store | Expr::Load | Expr::Bitwise(_) if !language.is_extended() => {
+            Some("extended brainfuck")
+        }
+        Expr::Loop(body) | Expr::Procedure(body) => extension(body, language),
+        _ => None,
+    })
+}
+
+/// Turn compiled expressions back into brainfuck source.
+pub fn to_source(exprs: &[Expr]) -> String {
+    let mut source = String::new();
+    let mut i = 0;
+
+    while i < exprs.len() {
+        let end = i + exprs[i..]
+            .iter()
+            .take_while(|expr| matches!(expr, Expr::MulAdd { .. }))
+            .count();
+
+        // Every multiplication of one loop reads the same counter,
+        // so they have to be written back as that single loop.
+        if end > i && exprs.get(end) == Some(&Expr::SetZero) {
+            source += &format!("[-{}]", mul_add_body(&exprs[i..end]));
+            i = end + 1;
+        } else {
+            source += &exprs[i].to_string();
+            i += 1;
+        }
+    }
+
+    source
+}
+
+/// Check that every bracket in `source` has a matching partner.
+pub fn validate(source: &str) -> Result<(), ParseError> {
+    validate_with_language(source, Language::Brainfuck)
+}
+
+/// Same as [`validate`], also pairing the parentheses of pbrain procedures.
+pub fn validate_with_language(source: &str, language: Language) -> Result<(), ParseError> {
+    let mut open = vec![];
+    let mut unmatched = vec![];
+
+    for (offset, c) in source.char_indices() {
+        match c {
+            '[' => open.push((offset, ']')),
+            '(' if language.has_procedures() => open.push((offset, ')')),
+            ']' | ')' if c == ']' || language.has_procedures() => match open.last() {
+                // A closing bracket only matches the innermost one still open.
+                Some((_, close)) if *close == c => {
+                    open.pop();
+                }
+                _ => unmatched.push(offset),
+            },
+            _ => (),
+        }
+    }
+
+    unmatched.extend(open.into_iter().map(|(offset, _)| offset));
+    if unmatched.is_empty() {
+        return Ok(());
+    }
+
+    unmatched.sort_unstable();
+    let brackets = unmatched
+        .into_iter()
+        .map(|offset| unmatched_bracket(source, offset, source[offset..].chars().next().unwrap()))
+        .collect();
+
+    Err(ParseError { brackets })
+}
+
+/// Describe the unmatched `bracket` spelled at `offset` in `source`.
+pub(crate) fn unmatched_bracket(source: &str, offset: usize, bracket: char) -> UnmatchedBracket {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+
+    UnmatchedBracket {
+        bracket,
+        offset,
+        line: source[..offset].matches('\n').count() + 1,
+        column: source[line_start..offset].chars().count() + 1,
+        snippet: source[line_start..line_end].trim_end().to_owned(),
+    }
+}
+
+/// Extended Type I programs and what they print.
+#[cfg(test)]
+pub(crate) fn extended_corpus() -> Vec<(&'static str, &'static [u8])> {
+    vec![
+        ("+.@+.", &[1]),
+        ("+[.@]+.", &[1]),
+        ("+++$>!.", &[3]),
+        ("+++!.", &[0]),
+        ("+++++}.", &[2]),
+        ("-{.", &[254]),
+        ("~.", &[255]),
+        ("+~.", &[254]),
+        ("+++$>+++++^.", &[6]),
+        ("+++$>+++++&.", &[1]),
+        ("+++$>+++++|.", &[7]),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn should_compile() {
         let source = "+[->+<]";
-        let tokens = Compiler::new(source.chars()).compile();
+        let tokens = Compiler::new(source.chars()).compile().unwrap();
 
         use Expr::*;
         assert_eq!(
@@ -111,4 +468,133 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn should_compile_after_loop() {
+        let source = "+[->+<]>.";
+        let tokens = Compiler::new(source.chars()).compile().unwrap();
+
+        use Expr::*;
+        assert_eq!(
+            tokens,
+            vec![
+                IncData(1),
+                Loop(vec![DecData(1), IncPtr(1), IncData(1), DecPtr(1)]),
+                IncPtr(1),
+                Output,
+            ]
+        )
+    }
+
+    #[test]
+    fn should_compile_nested_loops() {
+        let source = "++[>[-]<[>+<-]-]";
+        let tokens = Compiler::new(source.chars()).compile().unwrap();
+
+        use Expr::*;
+        assert_eq!(
+            tokens,
+            vec![
+                IncData(2),
+                Loop(vec![
+                    IncPtr(1),
+                    Loop(vec![DecData(1)]),
+                    DecPtr(1),
+                    Loop(vec![IncPtr(1), IncData(1), DecPtr(1), DecData(1)]),
+                    DecData(1),
+                ]),
+            ]
+        )
+    }
+
+    #[test]
+    fn should_record_spans() {
+        let source = "++ [>.]";
+        let mut compiler = Compiler::new(source.chars());
+        compiler.compile().unwrap();
+
+        let spans = compiler
+            .spans()
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec!["++", "[>.]", ">", "."]);
+    }
+
+    #[test]
+    fn should_round_trip() {
+        let source = "+++++[>++[>+>+<<-]<-]>>[-<+>]<.,";
+        let tokens = Compiler::new(source.chars()).compile().unwrap();
+
+        assert_eq!(to_source(&tokens), source);
+    }
+
+    #[test]
+    fn should_skip_leading_loop() {
+        let source = "[comment [with] nesting]+.";
+        let tokens = Compiler::new(source.chars()).compile().unwrap();
+
+        assert_eq!(tokens, vec![Expr::IncData(1), Expr::Output]);
+    }
+
+    fn program() -> impl Strategy<Value = String> {
+        "[-+<>.,]{0,8}".prop_recursive(4, 64, 4, |inner| {
+            prop::collection::vec(
+                prop_oneof![inner.clone(), inner.prop_map(|body| format!("[{body}]"))],
+                0..4,
+            )
+            .prop_map(|parts| parts.concat())
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn should_round_trip_balanced_programs(source in program()) {
+            // A leading loop never runs, so the compiler drops it.
+            let source = format!("+{source}");
+            let tokens = Compiler::new(source.chars()).compile().unwrap();
+
+            prop_assert_eq!(to_source(&tokens), source);
+        }
+    }
+
+    #[test]
+    fn should_report_unmatched_brackets() {
+        let source = "+[-\n]]>\n[[-]";
+        let error = Compiler::new(source.chars()).compile().unwrap_err();
+
+        let positions = error
+            .brackets
+            .iter()
+            .map(|b| (b.bracket, b.line, b.column, b.snippet.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(positions, vec![(']', 2, 2, "]]>"), ('[', 3, 1, "[[-]")]);
+    }
+
+    #[test]
+    fn should_compile_pbrain_procedures() {
+        let language = "pbrain".parse().unwrap();
+        let tokens = Compiler::with_language("+(>+:<):".chars(), language)
+            .compile()
+            .unwrap();
+
+        use Expr::*;
+        assert_eq!(
+            tokens,
+            vec![
+                IncData(1),
+                Procedure(vec![IncPtr(1), IncData(1), Call, DecPtr(1)]),
+                Call
+            ]
+        );
+        assert_eq!(to_source(&tokens), "+(>+:<):");
+
+        let error = validate_with_language("([)]", language).unwrap_err();
+        let brackets = error.brackets.iter().map(|b| b.bracket).collect::<Vec<_>>();
+        assert_eq!(brackets, vec!['(', ')']);
+        // Parentheses are comments in plain brainfuck.
+        assert!(validate("([)]").is_ok());
+    }
 }
@@ -0,0 +1,269 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tape::TapeMode;
+
+/// Number of bits in a single tape cell.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellWidth {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+/// What happens when `+` or `-` moves a cell past its range.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wrap around to the other end of the range.
+    #[default]
+    Wrap,
+
+    /// Stay at the smallest or largest value.
+    Saturate,
+
+    /// Stop the program with a runtime error.
+    Error,
+}
+
+/// What `,` stores in the current cell once the input is exhausted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EofMode {
+    /// Set the cell to zero.
+    Zero,
+
+    /// Set the cell to its largest value, `-1` when read as signed.
+    Max,
+
+    /// Leave the cell as it was.
+    #[default]
+    Unchanged,
+}
+
+/// How programs are executed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Walk the expression tree.
+    #[default]
+    Tree,
+
+    /// Flatten the tree into instructions with precomputed jumps first.
+    Bytecode,
+
+    /// Compile the tree to machine code with Cranelift.
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+/// Calls a pbrain program may nest unless configured otherwise.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// Commands understood on top of the eight of brainfuck.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    /// Only `><+-.,[]`, everything else is a comment.
+    #[default]
+    Brainfuck,
+
+    /// pbrain, where `(...)` defines the procedure numbered by the current cell
+    /// and `:` calls it, with at most `max_depth` calls in progress.
+    Pbrain { max_depth: usize },
+
+    /// Extended Brainfuck Type I, adding `@` to end the program, a storage
+    /// register set by `$` and read by `!`, and the bitwise `}{~^&|`.
+    Extended,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Config {
+    pub cell_width: CellWidth,
+    pub overflow: Overflow,
+    pub tape: TapeMode,
+    pub eof: EofMode,
+
+    /// Run the peephole optimizer before executing.
+    pub optimize: bool,
+    pub engine: Engine,
+    pub language: Language,
+}
+
+impl CellWidth {
+    /// Largest value a cell can hold.
+    pub fn max(self) -> u32 {
+        match self {
+            CellWidth::U8 => u8::MAX as u32,
+            CellWidth::U16 => u16::MAX as u32,
+            CellWidth::U32 => u32::MAX,
+        }
+    }
+}
+
+impl Language {
+    /// Whether `(`, `)` and `:` are commands.
+    pub fn has_procedures(&self) -> bool {
+        matches!(self, Language::Pbrain { .. })
+    }
+
+    /// Whether `@$!}{~^&|` are commands.
+    pub fn is_extended(&self) -> bool {
+        matches!(self, Language::Extended)
+    }
+
+    /// Calls that may be in progress at once.
+    pub fn max_depth(&self) -> usize {
+        match self {
+            Language::Pbrain { max_depth } => *max_depth,
+            Language::Brainfuck | Language::Extended => 0,
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "brainfuck" => Ok(Language::Brainfuck),
+            "pbrain" => Ok(Language::Pbrain {
+                max_depth: MAX_CALL_DEPTH,
+            }),
+            "extended" => Ok(Language::Extended),
+            _ => Err(format!("unknown language '{s}'")),
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(Engine::Tree),
+            "bytecode" => Ok(Engine::Bytecode),
+            #[cfg(feature = "jit")]
+            "jit" => Ok(Engine::Jit),
+            _ => Err(format!("unknown engine '{s}'")),
+        }
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(CellWidth::U8),
+            "16" => Ok(CellWidth::U16),
+            "32" => Ok(CellWidth::U32),
+            _ => Err(format!("cell width is 8, 16 or 32, not '{s}'")),
+        }
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Overflow::Wrap),
+            "saturate" => Ok(Overflow::Saturate),
+            "error" => Ok(Overflow::Error),
+            _ => Err(format!("unknown overflow mode '{s}'")),
+        }
+    }
+}
+
+impl FromStr for EofMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(EofMode::Zero),
+            "max" => Ok(EofMode::Max),
+            "unchanged" => Ok(EofMode::Unchanged),
+            _ => Err(format!("unknown eof mode '{s}'")),
+        }
+    }
+}
+
+impl Config {
+    /// Value of a cell after `,` hits the end of input.
+    pub fn eof_value(&self, value: u32) -> u32 {
+        match self.eof {
+            EofMode::Zero => 0,
+            EofMode::Max => self.cell_width.max(),
+            EofMode::Unchanged => value,
+        }
+    }
+
+    /// Add `amount` to a cell value, or `None` if that is an overflow error.
+    pub fn add(&self, value: u32, amount: u64) -> Option<u32> {
+        let max = self.cell_width.max() as u64;
+        let value = value as u64;
+
+        let sum = match self.overflow {
+            // `max` is all ones, so masking is the remainder.
+            Overflow::Wrap => value.wrapping_add(amount) & max,
+            Overflow::Saturate => value.saturating_add(amount).min(max),
+            Overflow::Error => value.checked_add(amount).filter(|sum| *sum <= max)?,
+        };
+
+        Some(sum as u32)
+    }
+
+    /// Subtract `amount` from a cell value, or `None` if that is an overflow error.
+    pub fn sub(&self, value: u32, amount: u64) -> Option<u32> {
+        let max = self.cell_width.max() as u64;
+        let value = value as u64;
+
+        let difference = match self.overflow {
+            Overflow::Wrap => value.wrapping_sub(amount) & max,
+            Overflow::Saturate => value.saturating_sub(amount),
+            Overflow::Error => value.checked_sub(amount)?,
+        };
+
+        Some(difference as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_apply_overflow_mode() {
+        let config = |overflow| Config {
+            overflow,
+            ..Default::default()
+        };
+
+        assert_eq!(config(Overflow::Wrap).add(250, 10), Some(4));
+        assert_eq!(config(Overflow::Wrap).sub(3, 260), Some(255));
+        assert_eq!(config(Overflow::Saturate).add(250, 10), Some(255));
+        assert_eq!(config(Overflow::Saturate).sub(3, 10), Some(0));
+        assert_eq!(config(Overflow::Error).add(250, 10), None);
+        assert_eq!(config(Overflow::Error).add(250, 5), Some(255));
+        assert_eq!(config(Overflow::Error).sub(3, 4), None);
+    }
+
+    #[test]
+    fn should_respect_cell_width() {
+        let config = Config {
+            cell_width: CellWidth::U16,
+            ..Default::default()
+        };
+
+        assert_eq!(config.add(255, 1), Some(256));
+        assert_eq!(config.add(65535, 1), Some(0));
+        assert_eq!(config.sub(0, 1), Some(65535));
+    }
+
+    #[test]
+    fn should_parse_option_names() {
+        assert_eq!("16".parse(), Ok(CellWidth::U16));
+        assert_eq!("saturate".parse(), Ok(Overflow::Saturate));
+        assert_eq!("max".parse(), Ok(EofMode::Max));
+        assert!("12".parse::<CellWidth>().is_err());
+    }
+}
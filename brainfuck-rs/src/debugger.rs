@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+};
+
+use crate::{
+    bytecode::Op,
+    config::Config,
+    error::{Error, ParseError},
+    program::{Outcome, Program},
+    BrainFuck,
+};
+
+/// Why the debugger handed control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested step is done.
+    Step,
+
+    /// The next instruction has a breakpoint.
+    Breakpoint,
+
+    /// The program ran to its end.
+    Finished,
+}
+
+/// Runs a program under control of a front end, one step,
+/// loop or stretch between breakpoints at a time.
+pub struct Debugger<R, W> {
+    brainfuck: BrainFuck<R, W>,
+    program: Program,
+
+    /// Instructions to stop before.
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<R: Read, W: Write> Debugger<R, W> {
+    /// Load `source` for debugging, with a breakpoint on the command after every `#`.
+    ///
+    /// Optimization is turned off so every instruction is a command in the source.
+    pub fn new(source: &str, config: Config, input: R, output: W) -> Result<Self, ParseError> {
+        let config = Config {
+            optimize: false,
+            ..config
+        };
+        let brainfuck = BrainFuck::with_config(config, input, output);
+        let program = brainfuck.load(source)?;
+
+        let mut debugger = Self {
+            brainfuck,
+            program,
+            breakpoints: BTreeSet::new(),
+        };
+
+        for (offset, _) in source.match_indices('#') {
+            debugger.add_breakpoint(offset);
+        }
+
+        Ok(debugger)
+    }
+
+    /// Stop before the first command at or after the byte offset `position`,
+    /// returning where the breakpoint ended up.
+    pub fn add_breakpoint(&mut self, position: usize) -> Option<usize> {
+        let spans = &self.program.chunk().spans;
+        let instruction = spans.partition_point(|span| span.start < position);
+        let span = spans.get(instruction)?;
+
+        self.breakpoints.insert(instruction);
+        Some(span.start)
+    }
+
+    /// Remove the breakpoint at the byte offset `position`, if there is one.
+    pub fn remove_breakpoint(&mut self, position: usize) -> bool {
+        let spans = &self.program.chunk().spans;
+        let found = self
+            .breakpoints
+            .iter()
+            .copied()
+            .find(|instruction| spans[*instruction].start == position);
+
+        match found {
+            Some(instruction) => self.breakpoints.remove(&instruction),
+            None => false,
+        }
+    }
+
+    /// Byte offsets of every breakpoint, in order.
+    pub fn breakpoints(&self) -> Vec<usize> {
+        let spans = &self.program.chunk().spans;
+        self.breakpoints
+            .iter()
+            .map(|instruction| spans[*instruction].start)
+            .collect()
+    }
+
+    /// Byte offset of the next command to run.
+    pub fn position(&self) -> Option<usize> {
+        self.program.position()
+    }
+
+    pub fn pointer(&self) -> isize {
+        self.brainfuck.tape.pointer()
+    }
+
+    /// Cells within `radius` of the pointer, with their indices.
+    pub fn tape(&self, radius: usize) -> Vec<(isize, u32)> {
+        self.brainfuck.tape.around(radius)
+    }
+
+    /// Run the next command.
+    pub fn step(&mut self) -> Result<Stop, Error> {
+        match self.brainfuck.resume(&mut self.program, 1)? {
+            Outcome::Finished => Ok(Stop::Finished),
+            Outcome::Suspended => Ok(Stop::Step),
+        }
+    }
+
+    /// Run the next command, or the whole loop or procedure call when it starts one.
+    pub fn step_over(&mut self) -> Result<Stop, Error> {
+        let pc = self.program.pc();
+        let depth = self.program.depth();
+        let end = match self.program.chunk().ops.get(pc) {
+            Some(Op::JumpIfZero(end)) => Some(*end as usize),
+            Some(Op::Call) => None,
+            _ => return self.step(),
+        };
+
+        // Still in the loop or call, which may recurse back to the same loop.
+        let inside = |program: &Program| {
+            program.depth() > depth || end.is_some_and(|end| program.pc() != end)
+        };
+
+        self.step()?;
+        while inside(&self.program) {
+            if let Some(stop) = self.interruption() {
+                return Ok(stop);
+            }
+            self.step()?;
+        }
+
+        Ok(Stop::Step)
+    }
+
+    /// Run until the next breakpoint or the end of the program.
+    pub fn resume(&mut self) -> Result<Stop, Error> {
+        // Leave the breakpoint the program may be sitting on.
+        self.step()?;
+
+        loop {
+            if let Some(stop) = self.interruption() {
+                return Ok(stop);
+            }
+            self.step()?;
+        }
+    }
+
+    /// Consume the debugger, returning the program's input and output streams.
+    pub fn into_io(self) -> (R, W) {
+        self.brainfuck.into_io()
+    }
+
+    fn interruption(&self) -> Option<Stop> {
+        if self.program.is_finished() {
+            Some(Stop::Finished)
+        } else if self.breakpoints.contains(&self.program.pc()) {
+            Some(Stop::Breakpoint)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger(source: &str) -> Debugger<&'static [u8], Vec<u8>> {
+        Debugger::new(source, Config::default(), &b""[..], vec![]).unwrap()
+    }
+
+    #[test]
+    fn should_stop_at_breakpoints() {
+        let mut debugger = debugger("++#>+++ >+");
+
+        assert_eq!(debugger.breakpoints(), vec![3]);
+        assert_eq!(debugger.add_breakpoint(7), Some(8));
+
+        assert_eq!(debugger.resume().unwrap(), Stop::Breakpoint);
+        assert_eq!(debugger.position(), Some(3));
+        assert_eq!(debugger.resume().unwrap(), Stop::Breakpoint);
+        assert_eq!(debugger.position(), Some(8));
+        assert_eq!(debugger.tape(1), vec![(0, 2), (1, 3), (2, 0)]);
+
+        assert!(debugger.remove_breakpoint(3));
+        assert_eq!(debugger.resume().unwrap(), Stop::Finished);
+    }
+
+    #[test]
+    fn should_step_over_loops() {
+        let mut debugger = debugger("++[>+++<-]>.");
+
+        assert_eq!(debugger.step().unwrap(), Stop::Step);
+        assert_eq!(debugger.position(), Some(2));
+        assert_eq!(debugger.step_over().unwrap(), Stop::Step);
+        assert_eq!(debugger.position(), Some(10));
+        assert_eq!(debugger.tape(1), vec![(0, 0), (1, 6)]);
+    }
+
+    #[test]
+    fn should_step_over_calls() {
+        let config = Config {
+            language: "pbrain".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut debugger = Debugger::new("+(>++<):>", config, &b""[..], vec![]).unwrap();
+
+        debugger.step().unwrap();
+        debugger.step().unwrap();
+        assert_eq!(debugger.position(), Some(7));
+        assert_eq!(debugger.step_over().unwrap(), Stop::Step);
+        assert_eq!(debugger.position(), Some(8));
+        assert_eq!(debugger.tape(1), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn should_stop_inside_loop_at_breakpoint() {
+        let mut debugger = debugger("++[>#+<-]");
+
+        debugger.step().unwrap();
+        assert_eq!(debugger.step_over().unwrap(), Stop::Breakpoint);
+        assert_eq!(debugger.position(), Some(5));
+    }
+}
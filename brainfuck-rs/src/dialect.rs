@@ -0,0 +1,389 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compiler::{unmatched_bracket, Compiler, Expr, Span},
+    error::{DialectError, ParseError},
+};
+
+/// Spellings of the eight commands in a language that only swaps
+/// each brainfuck command for a token of its own.
+///
+/// Whitespace inside a token matches any run of whitespace in a program,
+/// and anything that is not a token is a comment.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Dialect {
+    pub right: String,
+    pub left: String,
+    pub increment: String,
+    pub decrement: String,
+    pub output: String,
+    pub input: String,
+    pub open: String,
+    pub close: String,
+
+    /// Written between tokens when converting into this dialect.
+    #[serde(default)]
+    pub separator: String,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self::brainfuck()
+    }
+}
+
+impl Dialect {
+    pub fn brainfuck() -> Self {
+        Self::from_tokens([">", "<", "+", "-", ".", ",", "[", "]"], "")
+    }
+
+    pub fn ook() -> Self {
+        Self::paired("Ook")
+    }
+
+    pub fn blub() -> Self {
+        Self::paired("Blub")
+    }
+
+    /// One of the built in dialects, `brainfuck`, `ook` or `blub`.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "brainfuck" => Some(Self::brainfuck()),
+            "ook" => Some(Self::ook()),
+            "blub" => Some(Self::blub()),
+            _ => None,
+        }
+    }
+
+    pub fn from_toml(table: &str) -> Result<Self, DialectError> {
+        let dialect: Self = toml::from_str(table)?;
+        dialect.validate()?;
+        Ok(dialect)
+    }
+
+    pub fn from_json(table: &str) -> Result<Self, DialectError> {
+        let dialect: Self = serde_json::from_str(table)?;
+        dialect.validate()?;
+        Ok(dialect)
+    }
+
+    /// Read a token table, as JSON when the file ends in `.json` and as TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, DialectError> {
+        let table = fs::read_to_string(path)?;
+
+        match path.extension() {
+            Some(extension) if extension == "json" => Self::from_json(&table),
+            _ => Self::from_toml(&table),
+        }
+    }
+
+    /// Every command in `source` as a brainfuck character and the span of its token.
+    pub fn commands(&self, source: &str) -> Vec<(char, Span)> {
+        let tokens = self.tokens();
+        let mut commands = vec![];
+        let mut offset = 0;
+
+        while let Some(c) = source[offset..].chars().next() {
+            // The longest token wins when one starts with another.
+            let found = tokens
+                .iter()
+                .filter_map(|(command, token)| {
+                    Some((*command, match_token(&source[offset..], token)?))
+                })
+                .max_by_key(|(_, length)| *length);
+
+            match found {
+                Some((command, length)) => {
+                    commands.push((
+                        command,
+                        Span {
+                            start: offset,
+                            end: offset + length,
+                        },
+                    ));
+                    offset += length;
+                }
+                None => offset += c.len_utf8(),
+            }
+        }
+
+        commands
+    }
+
+    /// Rewrite `source` as plain brainfuck, dropping comments.
+    pub fn translate(&self, source: &str) -> String {
+        self.translation(source).program
+    }
+
+    /// Rewrite `source` as plain brainfuck, keeping where each command came from.
+    pub fn translation(&self, source: &str) -> Translation {
+        let (program, spans) = self.commands(source).into_iter().unzip();
+        Translation { program, spans }
+    }
+
+    /// Spell the brainfuck `program` in this dialect, dropping comments.
+    pub fn render(&self, program: &str) -> String {
+        let tokens = self.tokens();
+
+        program
+            .chars()
+            .filter_map(|c| tokens.iter().find(|(command, _)| *command == c))
+            .map(|(_, token)| *token)
+            .collect::<Vec<_>>()
+            .join(&self.separator)
+    }
+
+    /// Compile `source` into the same expressions as its brainfuck translation,
+    /// with unmatched brackets reported where they are in `source`.
+    pub fn compile(&self, source: &str) -> Result<Vec<Expr>, ParseError> {
+        let commands = self.commands(source);
+        let program = commands
+            .iter()
+            .map(|(command, _)| *command)
+            .collect::<String>();
+
+        // Every command is one byte of the translation.
+        Compiler::new(program.chars()).compile().map_err(|error| {
+            let brackets = error
+                .brackets
+                .into_iter()
+                .map(|bracket| {
+                    let (_, span) = commands[bracket.offset];
+                    unmatched_bracket(source, span.start, bracket.bracket)
+                })
+                .collect();
+
+            ParseError { brackets }
+        })
+    }
+
+    /// Tokens in the order of the brainfuck commands `><+-.,[]`.
+    fn tokens(&self) -> [(char, &str); 8] {
+        [
+            ('>', &self.right),
+            ('<', &self.left),
+            ('+', &self.increment),
+            ('-', &self.decrement),
+            ('.', &self.output),
+            (',', &self.input),
+            ('[', &self.open),
+            (']', &self.close),
+        ]
+    }
+
+    fn validate(&self) -> Result<(), DialectError> {
+        const NAMES: [&str; 8] = [
+            "right",
+            "left",
+            "increment",
+            "decrement",
+            "output",
+            "input",
+            "open",
+            "close",
+        ];
+
+        let tokens = self.tokens();
+        for (i, (_, token)) in tokens.iter().enumerate() {
+            if token.trim().is_empty() {
+                return Err(DialectError::EmptyToken(NAMES[i]));
+            }
+
+            if tokens[..i]
+                .iter()
+                .any(|(_, other)| words(other).eq(words(token)))
+            {
+                return Err(DialectError::DuplicateToken(token.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ook! and its kin, where each command is two of `<word>.`, `<word>?` and `<word>!`.
+    fn paired(word: &str) -> Self {
+        let [period, question, exclamation] = [".", "?", "!"].map(|mark| format!("{word}{mark}"));
+        let pair = |first: &str, second: &str| format!("{first} {second}");
+
+        Self {
+            right: pair(&period, &question),
+            left: pair(&question, &period),
+            increment: pair(&period, &period),
+            decrement: pair(&exclamation, &exclamation),
+            output: pair(&exclamation, &period),
+            input: pair(&period, &exclamation),
+            open: pair(&exclamation, &question),
+            close: pair(&question, &exclamation),
+            separator: " ".to_owned(),
+        }
+    }
+
+    fn from_tokens(tokens: [&str; 8], separator: &str) -> Self {
+        let [right, left, increment, decrement, output, input, open, close] =
+            tokens.map(str::to_owned);
+
+        Self {
+            right,
+            left,
+            increment,
+            decrement,
+            output,
+            input,
+            open,
+            close,
+            separator: separator.to_owned(),
+        }
+    }
+}
+
+/// A program rewritten as plain brainfuck by [`Dialect::translation`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Translation {
+    /// The brainfuck program, one byte per command.
+    pub program: String,
+
+    /// Span of the token each command was read from.
+    spans: Vec<Span>,
+}
+
+impl Translation {
+    /// Where `span` of the brainfuck program was in the dialect source.
+    pub fn source_span(&self, span: Span) -> Span {
+        let end = self.spans.last().map_or(0, |last| last.end);
+        let token = |index: usize| self.spans.get(index).copied();
+
+        Span {
+            start: token(span.start).map_or(end, |token| token.start),
+            end: token(span.end.saturating_sub(1)).map_or(end, |token| token.end),
+        }
+    }
+
+    /// Position in the brainfuck program of the first command
+    /// at or after `position` in the dialect source.
+    pub fn program_position(&self, position: usize) -> usize {
+        self.spans.partition_point(|span| span.start < position)
+    }
+}
+
+/// Translate `source` from one dialect to another.
+pub fn convert(source: &str, from: &Dialect, to: &Dialect) -> String {
+    to.render(&from.translate(source))
+}
+
+fn words(token: &str) -> impl Iterator<Item = &str> {
+    token.split_whitespace()
+}
+
+/// Length of the match of `token` at the start of `source`, if there is one.
+fn match_token(source: &str, token: &str) -> Option<usize> {
+    let mut length = 0;
+
+    for (i, word) in words(token).enumerate() {
+        if i > 0 {
+            let gap = source[length..].len() - source[length..].trim_start().len();
+            if gap == 0 {
+                return None;
+            }
+            length += gap;
+        }
+
+        if !source[length..].starts_with(word) {
+            return None;
+        }
+        length += word.len();
+    }
+
+    Some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    #[test]
+    fn should_compile_like_brainfuck() {
+        let ook = Dialect::ook().render(HELLO);
+        assert!(ook.starts_with("Ook. Ook. Ook. Ook."));
+
+        // Tokens may be split over lines.
+        let ook = ook.replace("Ook! Ook?", "Ook!\n  Ook?");
+        let exprs = Compiler::new(HELLO.chars()).compile().unwrap();
+
+        assert_eq!(Dialect::ook().compile(&ook).unwrap(), exprs);
+        assert_eq!(
+            crate::run(&Dialect::ook().translate(&ook), b"").unwrap(),
+            b"Hello World!\n"
+        );
+    }
+
+    #[test]
+    fn should_convert_between_dialects() {
+        let blub = convert(HELLO, &Dialect::brainfuck(), &Dialect::blub());
+        let ook = convert(&blub, &Dialect::blub(), &Dialect::ook());
+
+        assert_eq!(ook, Dialect::ook().render(HELLO));
+        assert_eq!(convert(&ook, &Dialect::ook(), &Dialect::brainfuck()), HELLO);
+    }
+
+    #[test]
+    fn should_load_token_tables() {
+        let toml = r#"
+            right = "moo"
+            left = "mOo"
+            increment = "MoO"
+            decrement = "MOo"
+            output = "OOM"
+            input = "oom"
+            open = "MOO"
+            close = "moo!"
+        "#;
+        let dialect = Dialect::from_toml(toml).unwrap();
+        let json = serde_json::to_string(&dialect).unwrap();
+
+        assert_eq!(Dialect::from_json(&json).unwrap(), dialect);
+        // `moo!` is longer than `moo`, and `x` is a comment.
+        assert_eq!(dialect.translate("MoO MOO moo x moo! OOM"), "+[>].");
+        assert!(matches!(
+            Dialect::from_toml(&toml.replace("\"moo!\"", "\" \"")),
+            Err(DialectError::EmptyToken("close"))
+        ));
+        assert!(matches!(
+            Dialect::from_toml(&toml.replace("\"MOO\"", "\"mOo\"")),
+            Err(DialectError::DuplicateToken(_))
+        ));
+    }
+
+    #[test]
+    fn should_report_brackets_in_dialect_source() {
+        let error = Dialect::ook().compile("Ook. Ook.\nOok! Ook?").unwrap_err();
+
+        let bracket = &error.brackets[0];
+        assert_eq!(bracket.bracket, '[');
+        assert_eq!((bracket.offset, bracket.line, bracket.column), (10, 2, 1));
+        assert_eq!(bracket.snippet, "Ook! Ook?");
+    }
+
+    #[test]
+    fn should_map_runtime_errors_to_dialect_source() {
+        // `+<` moves off the left end of the tape.
+        let source = "Ook. Ook. comment\nOok? Ook.";
+        let translation = Dialect::ook().translation(source);
+
+        let position = match crate::run(&translation.program, b"") {
+            Err(crate::error::Error::Runtime(error)) => error.position.unwrap(),
+            result => panic!("unexpected result: {result:?}"),
+        };
+        let span = translation.source_span(Span {
+            start: position,
+            end: position + 1,
+        });
+
+        assert_eq!(&source[span.start..span.end], "Ook? Ook.");
+        assert_eq!(translation.program_position(span.start), position);
+        assert_eq!(translation.program_position(span.start + 1), position + 1);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{fmt::Display, io};
+
+/// Every bracket in a program that has no matching partner.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub brackets: Vec<UnmatchedBracket>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UnmatchedBracket {
+    /// `'['` or `']'`, or `'('` or `')'` for pbrain procedures.
+    pub bracket: char,
+
+    /// Byte offset of the bracket in the source.
+    pub offset: usize,
+
+    /// 1-based line number.
+    pub line: usize,
+
+    /// 1-based column, counted in characters.
+    pub column: usize,
+
+    /// The source line containing the bracket.
+    pub snippet: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, bracket) in self.brackets.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{bracket}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for UnmatchedBracket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "unmatched '{}' at line {}, column {}",
+            self.bracket, self.line, self.column
+        )?;
+        writeln!(f, "    {}", self.snippet)?;
+        write!(f, "    {:>width$}", "^", width = self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RuntimeErrorKind {
+    /// `+` went past the largest cell value.
+    Overflow,
+
+    /// `-` went below zero.
+    Underflow,
+
+    /// `<` moved the pointer left of the first cell.
+    PointerUnderflow,
+
+    /// `>` moved the pointer right of the last cell.
+    PointerOverflow,
+
+    /// `:` called a procedure that was never defined.
+    UndefinedProcedure,
+
+    /// `:` went past the configured number of nested calls.
+    CallDepth,
+}
+
+/// A fault that stopped a running program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+
+    /// Index of the failing expression in a depth-first walk of the program.
+    pub instruction: usize,
+
+    /// Byte offset of the failing command in the source, when known.
+    pub position: Option<usize>,
+
+    /// Tape pointer at the time of the fault.
+    pub pointer: isize,
+
+    /// Value of the current cell at the time of the fault.
+    pub value: u32,
+}
+
+impl Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeErrorKind::Overflow => write!(f, "cell overflow"),
+            RuntimeErrorKind::Underflow => write!(f, "cell underflow"),
+            RuntimeErrorKind::PointerUnderflow => {
+                write!(f, "pointer moved before the start of the tape")
+            }
+            RuntimeErrorKind::PointerOverflow => {
+                write!(f, "pointer moved past the end of the tape")
+            }
+            RuntimeErrorKind::UndefinedProcedure => write!(f, "call to an undefined procedure"),
+            RuntimeErrorKind::CallDepth => write!(f, "procedure calls nested too deep"),
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{} at position {position}", self.kind)?,
+            None => write!(f, "{} at instruction {}", self.kind, self.instruction)?,
+        }
+
+        write!(f, " (pointer {}, value {})", self.pointer, self.value)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// A configuration a code generation backend cannot express.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UnsupportedError {
+    pub backend: &'static str,
+    pub feature: &'static str,
+}
+
+impl Display for UnsupportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the {} backend does not support {}",
+            self.backend, self.feature
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedError {}
+
+/// A snapshot that could not be written or read back.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The data does not start like a snapshot.
+    NotASnapshot,
+
+    /// Written by a version of the format this build cannot read.
+    UnsupportedVersion(u32),
+    Encoding(bincode::Error),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "not a brainfuck snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            SnapshotError::Encoding(error) => write!(f, "corrupt snapshot: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(error: bincode::Error) -> Self {
+        SnapshotError::Encoding(error)
+    }
+}
+
+/// A token table that could not be loaded.
+#[derive(Debug)]
+pub enum DialectError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+
+    /// A command is spelled as nothing but whitespace.
+    EmptyToken(&'static str),
+
+    /// Two commands are spelled the same way.
+    DuplicateToken(String),
+}
+
+impl Display for DialectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DialectError::Io(error) => write!(f, "{error}"),
+            DialectError::Toml(error) => write!(f, "invalid token table: {error}"),
+            DialectError::Json(error) => write!(f, "invalid token table: {error}"),
+            DialectError::EmptyToken(command) => write!(f, "no token for {command}"),
+            DialectError::DuplicateToken(token) => {
+                write!(f, "token '{token}' is used for two commands")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DialectError {}
+
+impl From<io::Error> for DialectError {
+    fn from(error: io::Error) -> Self {
+        DialectError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for DialectError {
+    fn from(error: toml::de::Error) -> Self {
+        DialectError::Toml(error)
+    }
+}
+
+impl From<serde_json::Error> for DialectError {
+    fn from(error: serde_json::Error) -> Self {
+        DialectError::Json(error)
+    }
+}
+
+/// Anything that can go wrong while compiling and running a program.
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Runtime(RuntimeError),
+    Unsupported(UnsupportedError),
+    Io(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(error) => write!(f, "{error}"),
+            Error::Runtime(error) => write!(f, "{error}"),
+            Error::Unsupported(error) => write!(f, "{error}"),
+            Error::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(error: RuntimeError) -> Self {
+        Error::Runtime(error)
+    }
+}
+
+impl From<UnsupportedError> for Error {
+    fn from(error: UnsupportedError) -> Self {
+        Error::Unsupported(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{
+    compiler::{to_source, Compiler, Expr, Span},
+    config::Language,
+    error::ParseError,
+};
+
+/// Layout of formatted programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Longest line to fill with commands. Comments are never wrapped.
+    pub width: usize,
+
+    /// Spaces per loop level.
+    pub indent: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 4,
+        }
+    }
+}
+
+/// Lay `source` out with one indentation level per loop.
+///
+/// Commands fill lines up to the style's width, and a loop stays on one line
+/// when it fits and holds no comments. Comments keep their text and go on
+/// lines of their own before the command that follows them.
+pub fn format(source: &str, language: Language, style: Style) -> Result<String, ParseError> {
+    let mut compiler = Compiler::with_language(source.chars(), language);
+
+    // Keep a leading loop, it is often a comment block.
+    let exprs = compiler.compile_continuation()?;
+    let items = Items {
+        source,
+        spans: compiler.spans(),
+        next: 0,
+    }
+    .block(&exprs, 0, source.len());
+
+    let mut printer = Printer {
+        style,
+        depth: 0,
+        line: String::new(),
+        output: String::new(),
+    };
+    printer.items(&items);
+    printer.flush();
+
+    Ok(printer.output)
+}
+
+/// Drop everything but commands from `source`, along with pairs like `+-`
+/// and `<>` that undo each other.
+///
+/// The pairs are only a no-op with wrapping cells on a tape the pointer
+/// does not leave.
+pub fn minify(source: &str, language: Language) -> Result<String, ParseError> {
+    let exprs = Compiler::with_language(source.chars(), language).compile_continuation()?;
+    let mut minified = String::new();
+
+    for c in to_source(&exprs).chars() {
+        let inverse = match c {
+            '+' => Some('-'),
+            '-' => Some('+'),
+            '>' => Some('<'),
+            '<' => Some('>'),
+            _ => None,
+        };
+
+        match inverse {
+            Some(inverse) if minified.ends_with(inverse) => {
+                minified.pop();
+            }
+            _ => minified.push(c),
+        }
+    }
+
+    Ok(minified)
+}
+
+/// A program as the formatter sees it.
+#[derive(Debug)]
+enum Item<'a> {
+    /// Source between two commands, without surrounding whitespace.
+    Comment(&'a str),
+
+    /// A run of one command.
+    Command(String),
+
+    /// A loop or procedure with its brackets.
+    Block {
+        open: char,
+        close: char,
+        body: Vec<Item<'a>>,
+    },
+}
+
+/// Pairs compiled expressions with their spans to find the comments between them.
+struct Items<'a> {
+    source: &'a str,
+    spans: &'a [Span],
+
+    /// Index of the next span, in the depth-first order of the tree.
+    next: usize,
+}
+
+impl<'a> Items<'a> {
+    /// The items of `exprs`, which were compiled from `source[start..end]`.
+    fn block(&mut self, exprs: &[Expr], start: usize, end: usize) -> Vec<Item<'a>> {
+        let mut items = vec![];
+        let mut position = start;
+
+        for expr in exprs {
+            let span = self.spans[self.next];
+            self.next += 1;
+            self.comment(&mut items, position, span.start);
+
+            let item = match expr {
+                Expr::Loop(body) | Expr::Procedure(body) => {
+                    let (open, close) = match expr {
+                        Expr::Loop(_) => ('[', ']'),
+                        _ => ('(', ')'),
+                    };
+
+                    // Inside the brackets, which are one byte each.
+                    let body = self.block(body, span.start + 1, span.end - 1);
+                    Item::Block { open, close, body }
+                }
+                expr => Item::Command(expr.to_string()),
+            };
+            items.push(item);
+
+            position = span.end;
+        }
+
+        self.comment(&mut items, position, end);
+        items
+    }
+
+    fn comment(&self, items: &mut Vec<Item<'a>>, start: usize, end: usize) {
+        let text = self.source[start..end].trim();
+        if !text.is_empty() {
+            items.push(Item::Comment(text));
+        }
+    }
+}
+
+struct Printer {
+    style: Style,
+    depth: usize,
+
+    /// Commands not yet written, without indentation.
+    line: String,
+    output: String,
+}
+
+impl Printer {
+    fn items(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Comment(text) => {
+                    self.flush();
+                    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                        self.write_line(line);
+                    }
+                }
+                Item::Command(text) => self.word(text),
+                Item::Block { open, close, body } => match flat(item) {
+                    Some(text) if self.indentation() + text.len() <= self.style.width => {
+                        self.word(&text)
+                    }
+                    _ => {
+                        self.flush();
+                        self.write_line(&open.to_string());
+
+                        self.depth += 1;
+                        self.items(body);
+                        self.flush();
+                        self.depth -= 1;
+
+                        self.write_line(&close.to_string());
+                    }
+                },
+            }
+        }
+    }
+
+    /// Add `text` to the current line, starting a new one if it would get too long.
+    fn word(&mut self, text: &str) {
+        let length = self.indentation() + self.line.len() + text.len();
+        if !self.line.is_empty() && length > self.style.width {
+            self.flush();
+        }
+
+        self.line += text;
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.write_line(&line);
+        }
+    }
+
+    fn write_line(&mut self, text: &str) {
+        self.output += &" ".repeat(self.indentation());
+        self.output += text;
+        self.output.push('\n');
+    }
+
+    fn indentation(&self) -> usize {
+        self.depth * self.style.indent
+    }
+}
+
+/// `item` on one line, unless it holds a comment.
+fn flat(item: &Item) -> Option<String> {
+    match item {
+        Item::Comment(_) => None,
+        Item::Command(text) => Some(text.clone()),
+        Item::Block { open, close, body } => {
+            let body = body.iter().map(flat).collect::<Option<String>>()?;
+            Some(format!("{open}{body}{close}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = include_str!("../scripts/hello_world.bf");
+
+    const COMMENTED: &str = "Print an exclamation mark
+        +++++ +++ counts eight times
+        [ > ++++ adding four
+            < - ] > + .  done";
+
+    fn commands(source: &str) -> String {
+        let exprs = Compiler::new(source.chars())
+            .compile_continuation()
+            .unwrap();
+        to_source(&exprs)
+    }
+
+    #[test]
+    fn should_indent_loops() {
+        let style = Style {
+            width: 12,
+            indent: 1,
+        };
+        let formatted = format("++++[>++[>+<-]<-] print it >>.", Language::Brainfuck, style);
+
+        assert_eq!(
+            formatted.unwrap(),
+            "++++\n[\n >++[>+<-]<-\n]\nprint it\n>>.\n"
+        );
+    }
+
+    #[test]
+    fn should_keep_comments_and_commands() {
+        let style = Style {
+            width: 30,
+            ..Default::default()
+        };
+        let formatted = format(COMMENTED, Language::Brainfuck, style).unwrap();
+
+        assert_eq!(
+            formatted,
+            "Print an exclamation mark\n++++++++\ncounts eight times\n[\n    >++++\n    adding four\n    <-\n]\n>+.\ndone\n"
+        );
+        assert_eq!(commands(&formatted), commands(COMMENTED));
+        assert_eq!(
+            format(&formatted, Language::Brainfuck, style).unwrap(),
+            formatted
+        );
+        assert_eq!(crate::run(&formatted, b"").unwrap(), b"!");
+    }
+
+    #[test]
+    fn should_format_procedures() {
+        let language = "pbrain".parse().unwrap();
+        let formatted = format("+(>+<) call :", language, Style::default());
+
+        assert_eq!(formatted.unwrap(), "+(>+<)\ncall\n:\n");
+    }
+
+    #[test]
+    fn should_minify() {
+        let minified = minify("[ skip ]++-- a +>< b <<>.", Language::Brainfuck).unwrap();
+        assert_eq!(minified, "[]+<.");
+
+        let minified = minify(HELLO, Language::Brainfuck).unwrap();
+        assert_eq!(crate::run(&minified, b"").unwrap(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn should_report_unmatched_brackets() {
+        assert!(format("+[", Language::Brainfuck, Style::default()).is_err());
+        assert!(minify("]", Language::Brainfuck).is_err());
+    }
+}
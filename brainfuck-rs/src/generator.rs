@@ -0,0 +1,179 @@
+use std::{iter, ops::RangeInclusive};
+
+/// Counter of the setup loop written by [`Effort::Quick`].
+const QUICK_FACTOR: u8 = 10;
+
+/// Counters of the setup loop tried by [`Effort::Search`].
+const FACTORS: RangeInclusive<u8> = 2..=24;
+
+/// Most cells the setup loop fills.
+const MAX_CELLS: usize = 8;
+
+/// How hard [`generate`] looks for a short program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Effort {
+    /// One setup loop counting down from ten.
+    #[default]
+    Quick,
+
+    /// Try every setup loop in a range of counters and cell counts, keeping the shortest program.
+    Search,
+}
+
+/// A brainfuck program that prints `text`, for the default 8-bit wrapping cells.
+///
+/// A multiplication loop first fills a few cells with multiples of its counter
+/// close to the bytes of `text`. Each byte is then printed from whichever cell
+/// is cheapest to walk to and adjust, so nearby values are reused.
+pub fn generate(text: &[u8], effort: Effort) -> String {
+    match effort {
+        Effort::Quick => Layout::new(QUICK_FACTOR, multiples(text, QUICK_FACTOR)).program(text),
+        Effort::Search => {
+            let mut best = Layout::default().program(text);
+
+            for factor in FACTORS {
+                let by_appearance = multiples(text, factor);
+                let mut by_value = by_appearance.clone();
+                by_value.sort_unstable();
+
+                for multiples in [by_appearance, by_value] {
+                    for cells in 1..=multiples.len() {
+                        let program =
+                            Layout::new(factor, multiples[..cells].to_vec()).program(text);
+                        if program.len() < best.len() {
+                            best = program;
+                        }
+                    }
+                }
+            }
+
+            best
+        }
+    }
+}
+
+/// Cells filled by a setup loop: cell 0 counts down from `factor`,
+/// and each later cell gains its multiple of `factor` per round.
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    factor: u8,
+    multiples: Vec<i32>,
+}
+
+impl Layout {
+    fn new(factor: u8, multiples: Vec<i32>) -> Self {
+        Self { factor, multiples }
+    }
+
+    fn program(&self, text: &[u8]) -> String {
+        let mut program = String::new();
+        let mut cells = vec![0u8];
+
+        if !self.multiples.is_empty() {
+            push_repeated(&mut program, '+', self.factor as usize);
+            program.push('[');
+            for &multiple in &self.multiples {
+                program.push('>');
+                push_signed(&mut program, multiple);
+                cells.push((self.factor as i32 * multiple).rem_euclid(256) as u8);
+            }
+            push_repeated(&mut program, '<', self.multiples.len());
+            program.push_str("-]");
+        }
+
+        let mut pointer: usize = 0;
+        for &byte in text {
+            let (cell, value) = cells
+                .iter()
+                .enumerate()
+                .min_by_key(|(cell, value)| pointer.abs_diff(*cell) + distance(**value, byte))
+                .map(|(cell, value)| (cell, *value))
+                .unwrap();
+
+            let moves = if cell > pointer { '>' } else { '<' };
+            push_repeated(&mut program, moves, pointer.abs_diff(cell));
+            push_signed(&mut program, byte.wrapping_sub(value) as i8 as i32);
+            program.push('.');
+
+            pointer = cell;
+            cells[cell] = byte;
+        }
+
+        program
+    }
+}
+
+/// The nonzero multiples of `factor` nearest the bytes of `text`,
+/// in order of first appearance and at most [`MAX_CELLS`] of them.
+///
+/// Bytes above 127 are reached by counting down from zero.
+fn multiples(text: &[u8], factor: u8) -> Vec<i32> {
+    let factor = factor as f64;
+    let mut multiples = vec![];
+
+    for &byte in text {
+        let multiple = (byte as i8 as f64 / factor).round() as i32;
+        if multiple != 0 && !multiples.contains(&multiple) {
+            multiples.push(multiple);
+        }
+    }
+
+    multiples.truncate(MAX_CELLS);
+    multiples
+}
+
+/// Commands needed to turn a cell holding `from` into `to`.
+fn distance(from: u8, to: u8) -> usize {
+    (to.wrapping_sub(from) as i8).unsigned_abs() as usize
+}
+
+/// `+` for a positive `count`, `-` for a negative one.
+fn push_signed(program: &mut String, count: i32) {
+    let c = if count < 0 { '-' } else { '+' };
+    push_repeated(program, c, count.unsigned_abs() as usize);
+}
+
+fn push_repeated(program: &mut String, c: char, count: usize) {
+    program.extend(iter::repeat_n(c, count));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn assert_prints(text: &[u8], effort: Effort) {
+        let program = generate(text, effort);
+        assert_eq!(crate::run(&program, b"").unwrap(), text, "{program}");
+    }
+
+    #[test]
+    fn should_print_text() {
+        let all = (0..=255).collect::<Vec<u8>>();
+
+        for text in [&b""[..], b"Hello, World!\n", b"\x00\xff\x80\x7f", &all] {
+            assert_prints(text, Effort::Quick);
+            assert_prints(text, Effort::Search);
+        }
+    }
+
+    #[test]
+    fn should_search_for_shorter_programs() {
+        let text = b"Hello, World!\n";
+        let quick = generate(text, Effort::Quick);
+        let search = generate(text, Effort::Search);
+
+        assert!(search.len() <= quick.len());
+        assert!(quick.len() < text.iter().map(|&byte| byte as usize).sum());
+    }
+
+    proptest! {
+        #[test]
+        fn should_round_trip_bytes(text in prop::collection::vec(any::<u8>(), 0..64)) {
+            for effort in [Effort::Quick, Effort::Search] {
+                let program = generate(&text, effort);
+                prop_assert_eq!(crate::run(&program, b"").unwrap(), text.clone());
+            }
+        }
+    }
+}
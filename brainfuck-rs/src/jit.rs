@@ -0,0 +1,674 @@
+use std::{
+    io::{self, Read, Write},
+    mem,
+};
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+    Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use crate::{
+    codegen::tape_layout,
+    compiler::{BitOp, Expr},
+    config::{Config, EofMode, Language, Overflow},
+    error::{Error, RuntimeError, RuntimeErrorKind, UnsupportedError},
+    BrainFuck,
+};
+
+/// Returned by compiled code that ran to the end.
+const DONE: i64 = -1;
+
+/// Returned by `read_byte` at the end of input.
+const EOF: i64 = -1;
+
+/// Returned by `read_byte` and `write_byte` when the stream failed.
+const FAILED: i64 = -2;
+
+/// Byte offset of [`Exit::storage`].
+const STORAGE_OFFSET: i32 = 2 * mem::size_of::<i64>() as i32;
+
+/// What compiled code leaves behind when it returns.
+#[repr(C)]
+struct Exit {
+    pointer: i64,
+
+    /// Where the pointer would have gone on a bounds fault.
+    target: i64,
+
+    /// The Extended Type I storage register, read on entry and written on return.
+    storage: u32,
+}
+
+/// The streams I/O callbacks work on.
+struct Streams<'a, R, W> {
+    input: &'a mut R,
+    output: &'a mut W,
+    error: Option<io::Error>,
+}
+
+/// `fn(cells: *mut u32, exit: *mut Exit, streams: *mut Streams) -> i64`,
+/// returning [`DONE`] or the index of the failing expression.
+type Compiled = unsafe extern "C" fn(*mut u32, *mut Exit, *mut u8) -> i64;
+
+impl<R: Read, W: Write> BrainFuck<R, W> {
+    /// Compile `exprs` to machine code and run it against the current tape.
+    ///
+    /// Only wrapping cells on tapes that never grow are supported.
+    pub fn execute_jit(&mut self, exprs: &[Expr]) -> Result<(), Error> {
+        // Procedures need a call stack, everything else runs on a tape of one size.
+        let layout = tape_layout(
+            "jit",
+            exprs,
+            &self.config,
+            Language::Extended,
+            &[Overflow::Wrap],
+        )?;
+
+        let unsupported = UnsupportedError {
+            backend: "jit",
+            feature: "this host",
+        };
+        let mut module = module::<R, W>().ok_or(unsupported)?;
+        let function = Translator::translate(&mut module, exprs, &self.config, layout);
+        let result = self.call_compiled(function);
+
+        // Nothing refers to the code anymore.
+        unsafe { module.free_memory() };
+        result
+    }
+
+    fn call_compiled(&mut self, function: Compiled) -> Result<(), Error> {
+        let (cells, pointer) = self.tape.fixed_parts();
+        let mut exit = Exit {
+            pointer: *pointer as i64,
+            target: 0,
+            storage: self.storage,
+        };
+        let mut streams = Streams {
+            input: &mut self.input,
+            output: &mut self.output,
+            error: None,
+        };
+
+        // The pointer is checked against the length before every access.
+        let instruction = unsafe {
+            function(
+                cells.as_mut_ptr(),
+                &mut exit,
+                &mut streams as *mut Streams<R, W> as *mut u8,
+            )
+        };
+
+        *pointer = exit.pointer as isize;
+        self.storage = exit.storage;
+        if let Some(error) = streams.error {
+            return Err(error.into());
+        }
+        if instruction == DONE {
+            return Ok(());
+        }
+
+        let kind = if exit.target < 0 {
+            RuntimeErrorKind::PointerUnderflow
+        } else {
+            RuntimeErrorKind::PointerOverflow
+        };
+
+        Err(Error::Runtime(RuntimeError {
+            kind,
+            instruction: instruction as usize,
+            position: None,
+            pointer: exit.pointer as isize,
+            value: cells[exit.pointer as usize],
+        }))
+    }
+}
+
+unsafe extern "C" fn read_byte<R: Read, W: Write>(streams: *mut u8) -> i64 {
+    let streams = &mut *(streams as *mut Streams<R, W>);
+
+    // Make sure any prompt is visible before blocking on input.
+    let mut byte = [0];
+    let result = streams
+        .output
+        .flush()
+        .and_then(|_| streams.input.read_exact(&mut byte));
+
+    match result {
+        Ok(()) => byte[0] as i64,
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => EOF,
+        Err(error) => {
+            streams.error = Some(error);
+            FAILED
+        }
+    }
+}
+
+unsafe extern "C" fn write_byte<R: Read, W: Write>(streams: *mut u8, byte: i64) -> i64 {
+    let streams = &mut *(streams as *mut Streams<R, W>);
+
+    match streams.output.write_all(&[byte as u8]) {
+        Ok(()) => 0,
+        Err(error) => {
+            streams.error = Some(error);
+            FAILED
+        }
+    }
+}
+
+/// A module for the host, with the I/O callbacks for `R` and `W` linked in.
+fn module<R: Read, W: Write>() -> Option<JITModule> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").ok()?;
+
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flags))
+        .ok()?;
+
+    let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+    builder.symbol("read_byte", read_byte::<R, W> as *const u8);
+    builder.symbol("write_byte", write_byte::<R, W> as *const u8);
+
+    Some(JITModule::new(builder))
+}
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    config: &'a Config,
+    length: i64,
+    wrapping: bool,
+
+    cells: Value,
+    exit: Value,
+    streams: Value,
+    pointer: Variable,
+    storage: Variable,
+    read_byte: FuncRef,
+    write_byte: FuncRef,
+
+    /// Block leaving with a bounds fault, taking the instruction and target.
+    fault: Block,
+
+    /// Index of the expression being translated in a depth-first walk.
+    instruction: i64,
+}
+
+impl Translator<'_> {
+    /// Compile `exprs` for a tape with the `(length, wrapping)` layout.
+    fn translate(
+        module: &mut JITModule,
+        exprs: &[Expr],
+        config: &Config,
+        layout: (usize, bool),
+    ) -> Compiled {
+        let pointer_type = module.target_config().pointer_type();
+
+        let mut io = module.make_signature();
+        io.params.push(AbiParam::new(pointer_type));
+        io.returns.push(AbiParam::new(types::I64));
+        let read_byte = module
+            .declare_function("read_byte", Linkage::Import, &io)
+            .expect("callbacks are declared once");
+        io.params.push(AbiParam::new(types::I64));
+        let write_byte = module
+            .declare_function("write_byte", Linkage::Import, &io)
+            .expect("callbacks are declared once");
+
+        let mut context = module.make_context();
+        let signature = &mut context.func.signature;
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.returns.push(AbiParam::new(types::I64));
+        let id = module
+            .declare_function("run", Linkage::Local, &context.func.signature)
+            .expect("run is declared once");
+
+        Self::build(
+            module,
+            &mut context,
+            exprs,
+            config,
+            layout,
+            read_byte,
+            write_byte,
+        );
+
+        module
+            .define_function(id, &mut context)
+            .expect("generated code is valid");
+        module.clear_context(&mut context);
+        module.finalize_definitions().expect("generated code links");
+
+        unsafe { mem::transmute::<*const u8, Compiled>(module.get_finalized_function(id)) }
+    }
+
+    fn build(
+        module: &mut JITModule,
+        context: &mut Context,
+        exprs: &[Expr],
+        config: &Config,
+        (length, wrapping): (usize, bool),
+        read_byte: cranelift_module::FuncId,
+        write_byte: cranelift_module::FuncId,
+    ) {
+        let mut function_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut function_context);
+        let read_byte = module.declare_func_in_func(read_byte, builder.func);
+        let write_byte = module.declare_func_in_func(write_byte, builder.func);
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let [cells, exit, streams] = builder.block_params(entry)[..] else {
+            unreachable!()
+        };
+
+        let pointer = Variable::from_u32(0);
+        builder.declare_var(pointer, types::I64);
+        let start = builder.ins().load(types::I64, MemFlags::trusted(), exit, 0);
+        builder.def_var(pointer, start);
+
+        let storage = Variable::from_u32(1);
+        builder.declare_var(storage, types::I32);
+        let initial = builder
+            .ins()
+            .load(types::I32, MemFlags::trusted(), exit, STORAGE_OFFSET);
+        builder.def_var(storage, initial);
+
+        let fault = builder.create_block();
+        builder.append_block_param(fault, types::I64);
+        builder.append_block_param(fault, types::I64);
+
+        let mut translator = Translator {
+            builder,
+            config,
+            length: length as i64,
+            wrapping,
+            cells,
+            exit,
+            streams,
+            pointer,
+            storage,
+            read_byte,
+            write_byte,
+            fault,
+            instruction: 0,
+        };
+
+        translator.block(exprs);
+        let done = translator.builder.ins().iconst(types::I64, DONE);
+        translator.leave(done);
+
+        // Store the pointer and the target before leaving with the instruction.
+        let builder = &mut translator.builder;
+        builder.switch_to_block(fault);
+        builder.seal_block(fault);
+        let [instruction, target] = builder.block_params(fault)[..] else {
+            unreachable!()
+        };
+        builder.ins().store(
+            MemFlags::trusted(),
+            target,
+            exit,
+            mem::size_of::<i64>() as i32,
+        );
+        translator.leave(instruction);
+
+        translator.builder.finalize();
+    }
+
+    fn block(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let instruction = self.instruction;
+        self.instruction += 1;
+
+        match expr {
+            Expr::IncPtr(n) => self.move_by(*n as i64, instruction),
+            Expr::DecPtr(n) => self.move_by(-(*n as i64), instruction),
+            Expr::IncData(value) => self.add(0, *value as i64, instruction),
+            Expr::DecData(value) => self.add(0, -(*value as i64), instruction),
+            Expr::IncDataAt { offset, value } => self.add(*offset, *value as i64, instruction),
+            Expr::DecDataAt { offset, value } => self.add(*offset, -(*value as i64), instruction),
+            Expr::Procedure(_) | Expr::Call => unreachable!("procedures are rejected up front"),
+            Expr::End => {
+                let done = self.builder.ins().iconst(types::I64, DONE);
+                self.leave(done);
+
+                // Nothing reaches what follows, but it still needs a block.
+                let after = self.builder.create_block();
+                self.builder.switch_to_block(after);
+                self.builder.seal_block(after);
+            }
+            Expr::Store => {
+                let address = self.address(0, instruction);
+                let cell = self.load(address);
+                self.builder.def_var(self.storage, cell);
+            }
+            Expr::Load => {
+                let address = self.address(0, instruction);
+                let storage = self.builder.use_var(self.storage);
+                self.store(address, storage);
+            }
+            Expr::Bitwise(op) => self.bitwise(*op, instruction),
+            Expr::SetZero => {
+                let zero = self.builder.ins().iconst(types::I32, 0);
+                let address = self.address(0, instruction);
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), zero, address, 0);
+            }
+            Expr::Output => {
+                let address = self.address(0, instruction);
+                let cell = self.load(address);
+                let byte = self.builder.ins().uextend(types::I64, cell);
+                let call = self
+                    .builder
+                    .ins()
+                    .call(self.write_byte, &[self.streams, byte]);
+                let result = self.builder.inst_results(call)[0];
+                self.check_io(result, instruction);
+            }
+            Expr::Input => self.input(instruction),
+            Expr::Loop(body) => {
+                self.repeat(|translator| translator.block(body));
+            }
+            Expr::Scan(step) => {
+                self.repeat(|translator| translator.move_by(*step as i64, instruction));
+            }
+            Expr::MulAdd { offset, factor } => {
+                let address = self.address(0, instruction);
+                let cell = self.load(address);
+
+                let apply = self.builder.create_block();
+                let after = self.builder.create_block();
+                self.builder.ins().brif(cell, apply, &[], after, &[]);
+                self.builder.switch_to_block(apply);
+                self.builder.seal_block(apply);
+
+                let product = self.builder.ins().imul_imm(cell, *factor as u32 as i64);
+                let address = self.address(*offset, instruction);
+                let target = self.load(address);
+                let sum = self.builder.ins().iadd(target, product);
+                self.store(address, sum);
+
+                self.builder.ins().jump(after, &[]);
+                self.builder.switch_to_block(after);
+                self.builder.seal_block(after);
+            }
+        }
+    }
+
+    /// Run `body` while the current cell is not zero.
+    fn repeat(&mut self, body: impl FnOnce(&mut Self)) {
+        let header = self.builder.create_block();
+        let inside = self.builder.create_block();
+        let after = self.builder.create_block();
+
+        self.builder.ins().jump(header, &[]);
+        self.builder.switch_to_block(header);
+
+        // The current cell is always in bounds, no check needed.
+        let pointer = self.builder.use_var(self.pointer);
+        let address = self.cell_address(pointer);
+        let cell = self.load(address);
+        self.builder.ins().brif(cell, inside, &[], after, &[]);
+
+        self.builder.switch_to_block(inside);
+        self.builder.seal_block(inside);
+        body(self);
+        self.builder.ins().jump(header, &[]);
+        self.builder.seal_block(header);
+
+        self.builder.switch_to_block(after);
+        self.builder.seal_block(after);
+    }
+
+    fn input(&mut self, instruction: i64) {
+        let call = self.builder.ins().call(self.read_byte, &[self.streams]);
+        let result = self.builder.inst_results(call)[0];
+        self.check_io(result, instruction);
+
+        let address = self.address(0, instruction);
+        let eof = match self.config.eof {
+            EofMode::Zero => Some(0),
+            EofMode::Max => Some(self.config.cell_width.max()),
+            EofMode::Unchanged => None,
+        };
+
+        let is_eof = self.builder.ins().icmp_imm(IntCC::Equal, result, EOF);
+        let byte = self.builder.ins().ireduce(types::I32, result);
+        let value = match eof {
+            Some(eof) => {
+                let eof = self.builder.ins().iconst(types::I32, eof as i64);
+                self.builder.ins().select(is_eof, eof, byte)
+            }
+            None => {
+                let cell = self.load(address);
+                self.builder.ins().select(is_eof, cell, byte)
+            }
+        };
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, address, 0);
+    }
+
+    /// Leave through the fault block when a callback reported a failure.
+    fn check_io(&mut self, result: Value, instruction: i64) {
+        let failed = self.builder.ins().icmp_imm(IntCC::Equal, result, FAILED);
+        let instruction = self.builder.ins().iconst(types::I64, instruction);
+        let pointer = self.builder.use_var(self.pointer);
+        self.branch_to_fault(failed, instruction, pointer);
+    }
+
+    fn bitwise(&mut self, op: BitOp, instruction: i64) {
+        let address = self.address(0, instruction);
+        let cell = self.load(address);
+        let storage = self.builder.use_var(self.storage);
+
+        // Storing cuts the left shift and the flipped bits down to the cell width.
+        let ins = self.builder.ins();
+        let value = match op {
+            BitOp::ShiftRight => ins.ushr_imm(cell, 1),
+            BitOp::ShiftLeft => ins.ishl_imm(cell, 1),
+            BitOp::Not => ins.bnot(cell),
+            BitOp::Xor => ins.bxor(cell, storage),
+            BitOp::And => ins.band(cell, storage),
+            BitOp::Or => ins.bor(cell, storage),
+        };
+        self.store(address, value);
+    }
+
+    fn add(&mut self, offset: i32, value: i64, instruction: i64) {
+        let address = self.address(offset, instruction);
+        let cell = self.load(address);
+        let sum = self.builder.ins().iadd_imm(cell, value as u32 as i64);
+        self.store(address, sum);
+    }
+
+    fn move_by(&mut self, by: i64, instruction: i64) {
+        let target = self.offset_pointer(by, instruction);
+        self.builder.def_var(self.pointer, target);
+    }
+
+    /// Index of the cell `offset` cells away, wrapped or bounds checked.
+    fn offset_pointer(&mut self, offset: i64, instruction: i64) -> Value {
+        let pointer = self.builder.use_var(self.pointer);
+        if offset == 0 {
+            return pointer;
+        }
+
+        if self.wrapping {
+            // Both are below the length, so one subtraction brings it back.
+            let offset = offset.rem_euclid(self.length);
+            let target = self.builder.ins().iadd_imm(pointer, offset);
+            let wrapped = self.builder.ins().iadd_imm(target, -self.length);
+            let over =
+                self.builder
+                    .ins()
+                    .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, target, self.length);
+            return self.builder.ins().select(over, wrapped, target);
+        }
+
+        // Negative targets compare as huge unsigned numbers.
+        let target = self.builder.ins().iadd_imm(pointer, offset);
+        let outside =
+            self.builder
+                .ins()
+                .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, target, self.length);
+        let instruction = self.builder.ins().iconst(types::I64, instruction);
+        self.branch_to_fault(outside, instruction, target);
+
+        target
+    }
+
+    fn branch_to_fault(&mut self, condition: Value, instruction: Value, target: Value) {
+        let next = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, self.fault, &[instruction, target], next, &[]);
+        self.builder.switch_to_block(next);
+        self.builder.seal_block(next);
+    }
+
+    fn address(&mut self, offset: i32, instruction: i64) -> Value {
+        let index = self.offset_pointer(offset as i64, instruction);
+        self.cell_address(index)
+    }
+
+    fn cell_address(&mut self, index: Value) -> Value {
+        let bytes = self.builder.ins().ishl_imm(index, 2);
+        self.builder.ins().iadd(self.cells, bytes)
+    }
+
+    fn load(&mut self, address: Value) -> Value {
+        self.builder
+            .ins()
+            .load(types::I32, MemFlags::trusted(), address, 0)
+    }
+
+    /// Store `value` cut down to the cell width.
+    fn store(&mut self, address: Value, value: Value) {
+        let max = self.config.cell_width.max();
+        let value = match max {
+            u32::MAX => value,
+            max => self.builder.ins().band_imm(value, max as i64),
+        };
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, address, 0);
+    }
+
+    fn leave(&mut self, result: Value) {
+        let pointer = self.builder.use_var(self.pointer);
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), pointer, self.exit, 0);
+        let storage = self.builder.use_var(self.storage);
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), storage, self.exit, STORAGE_OFFSET);
+        self.builder.ins().return_(&[result]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::{corpus, faults, interpret},
+        compiler::extended_corpus,
+        config::{Engine, Language},
+        run_with_config,
+    };
+
+    #[test]
+    fn should_match_interpreter() {
+        for (config, program, input) in corpus().into_iter().chain(faults()) {
+            let jit = Config {
+                engine: Engine::Jit,
+                ..config
+            };
+
+            assert_eq!(
+                interpret(program, input, jit),
+                interpret(program, input, config),
+                "output differs for {program}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_run_extended_commands() {
+        let config = Config {
+            engine: Engine::Jit,
+            language: Language::Extended,
+            ..Default::default()
+        };
+
+        for (program, output) in extended_corpus() {
+            assert_eq!(
+                run_with_config(program, b"", config).unwrap(),
+                output,
+                "output differs for {program}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_report_pointer_out_of_bounds() {
+        let config = Config {
+            engine: Engine::Jit,
+            ..Default::default()
+        };
+
+        match run_with_config("+>+<<", b"", config) {
+            Err(Error::Runtime(error)) => {
+                assert_eq!(error.kind, RuntimeErrorKind::PointerUnderflow);
+                assert_eq!(error.position, Some(3));
+                assert_eq!(error.pointer, 1);
+            }
+            result => panic!("expected a runtime error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn should_reject_checked_cells() {
+        let config = Config {
+            engine: Engine::Jit,
+            overflow: Overflow::Error,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            run_with_config("+", b"", config),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn should_reject_procedures() {
+        let config = Config {
+            engine: Engine::Jit,
+            language: "pbrain".parse().unwrap(),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            run_with_config("+(>[+]<):", b"", config),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
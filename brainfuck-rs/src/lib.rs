@@ -1,82 +1,743 @@
-mod compiler;
+pub mod bytecode;
+pub mod codegen;
+pub mod compiler;
+pub mod config;
+pub mod debugger;
+pub mod dialect;
+pub mod error;
+pub mod formatter;
+pub mod generator;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod optimizer;
+pub mod profiler;
+pub mod program;
+pub mod repl;
+pub mod snapshot;
+pub mod tape;
 
-use std::io::stdin;
+use std::{
+    collections::HashMap,
+    io::{self, stdin, stdout, Read, Stdin, Stdout, Write},
+    rc::Rc,
+};
 
-const TAPE_LENGTH: usize = 30000;
+use bytecode::Op;
+use compiler::{node_count, BitOp, Compiler, Expr, Span};
+use config::{Config, Engine};
+use error::{Error, RuntimeError, RuntimeErrorKind};
+use optimizer::optimize_with_spans;
+use tape::Tape;
 
-pub struct BrainFuck {
-    tape: [u8; TAPE_LENGTH],
-    ptr: usize,
-    is_looping: bool,
-    loop_stack: Vec<usize>,
-    inner_loops: usize,
+pub struct BrainFuck<R = Stdin, W = Stdout> {
+    config: Config,
+    tape: Tape,
+    input: R,
+    output: W,
+
+    /// pbrain procedures defined while walking the tree, by number.
+    procedures: HashMap<u32, Rc<[Expr]>>,
+
+    /// pbrain procedures defined by the bytecode engine, by number.
+    procedure_ops: HashMap<u32, Rc<[Op]>>,
+
+    /// pbrain calls in progress while running a procedure's instructions.
+    depth: usize,
+
+    /// The Extended Type I storage register.
+    storage: u32,
+}
+
+/// What a block of the tree walk is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Block {
+    Program,
+    Loop,
+    Procedure,
+}
+
+/// A block the tree walk is in, see [`BrainFuck::execute`].
+struct Frame<'a> {
+    exprs: &'a [Expr],
+    block: Block,
+
+    /// The expression running now, or the block or call it is in
+    /// for frames below the top.
+    index: usize,
+}
+
+impl<'a> Frame<'a> {
+    fn new(exprs: &'a [Expr], block: Block) -> Self {
+        Self {
+            exprs,
+            block,
+            index: 0,
+        }
+    }
 }
 
 impl BrainFuck {
     pub fn new() -> Self {
+        Self::with_io(stdin(), stdout())
+    }
+}
+
+impl<R: Read, W: Write> BrainFuck<R, W> {
+    /// Create an interpreter reading `,` from `input` and writing `.` to `output`.
+    pub fn with_io(input: R, output: W) -> Self {
+        Self::with_config(Config::default(), input, output)
+    }
+
+    pub fn with_config(config: Config, input: R, output: W) -> Self {
         Self {
-            tape: [0; 30000],
-            ptr: 0,
-            is_looping: false,
-            loop_stack: vec![],
-            inner_loops: 0,
+            config,
+            tape: Tape::new(config.tape),
+            input,
+            output,
+            procedures: HashMap::new(),
+            procedure_ops: HashMap::new(),
+            depth: 0,
+            storage: 0,
         }
     }
 
-    pub fn compile(&mut self, program: &str) {
-        let chars = program.chars().collect::<Vec<_>>();
+    pub fn compile(&mut self, program: &str) -> Result<(), Error> {
+        let mut compiler = Compiler::with_language(program.chars(), self.config.language);
+        let exprs = compiler.compile()?;
+        self.run_compiled(exprs, compiler.spans().to_vec())
+    }
 
-        let mut i = 0;
-        while i < program.len() {
-            let c = chars[i];
-            if self.is_looping {
-                if c == '[' {
-                    self.inner_loops += 1
-                }
+    /// Run `program` on the tape as the previous program left it.
+    ///
+    /// Unlike [`BrainFuck::compile`], a leading loop runs when the current cell is not zero.
+    pub fn continue_with(&mut self, program: &str) -> Result<(), Error> {
+        let mut compiler = Compiler::with_language(program.chars(), self.config.language);
+        let exprs = compiler.compile_continuation()?;
+        self.run_compiled(exprs, compiler.spans().to_vec())
+    }
 
-                if c == ']' {
-                    if self.inner_loops == 0 {
-                        self.is_looping = false;
-                    } else {
-                        self.inner_loops -= 1;
-                    }
-                }
+    fn run_compiled(&mut self, mut exprs: Vec<Expr>, mut spans: Vec<Span>) -> Result<(), Error> {
+        if self.config.optimize {
+            (exprs, spans) = optimize_with_spans(exprs, &spans, self.config.overflow);
+        }
 
-                continue;
+        let result = match self.config.engine {
+            Engine::Tree => self.execute(&exprs),
+            Engine::Bytecode => {
+                let chunk = bytecode::lower(&exprs, &spans);
+                spans = chunk.spans;
+                self.execute_ops(&chunk.ops)
+            }
+            #[cfg(feature = "jit")]
+            Engine::Jit => self.execute_jit(&exprs),
+        };
+        self.output.flush()?;
+
+        match result {
+            Err(Error::Runtime(mut error)) => {
+                error.position = spans.get(error.instruction).map(|s| s.start);
+                Err(error.into())
             }
+            result => result,
+        }
+    }
 
-            match c {
-                '+' => self.tape[self.ptr] += 1,
-                '-' => self.tape[self.ptr] -= 1,
-                '>' => self.ptr += 1,
-                '<' => self.ptr -= 1,
-                '.' => print!("{}", self.tape[self.ptr] as char),
-                ',' => {
-                    let mut line = String::new();
-                    stdin().read_line(&mut line).unwrap();
-                    match line.chars().nth(0) {
-                        Some(char) => self.tape[self.ptr] = char as u8,
-                        None => (),
+    /// Run an already compiled program against the current tape.
+    ///
+    /// Loops and pbrain calls go on a stack of frames rather than the native one,
+    /// so even calls nested to the configured depth cannot overflow it.
+    pub fn execute(&mut self, exprs: &[Expr]) -> Result<(), Error> {
+        // Procedures from earlier programs, kept alive while this one runs.
+        let earlier = self.procedures.clone();
+        let mut procedures: HashMap<u32, &[Expr]> = earlier
+            .iter()
+            .map(|(&number, body)| (number, &body[..]))
+            .collect();
+        let mut frames = vec![Frame::new(exprs, Block::Program)];
+        let mut depth = 0;
+
+        while let Some(frame) = frames.last_mut() {
+            let Some(expr) = frame.exprs.get(frame.index) else {
+                match frame.block {
+                    Block::Loop if self.tape.get() != 0 => frame.index = 0,
+                    block => {
+                        if block == Block::Procedure {
+                            depth -= 1;
+                        }
+                        frames.pop();
+                        if let Some(parent) = frames.last_mut() {
+                            parent.index += 1;
+                        }
                     }
                 }
-                '[' => {
-                    if self.tape[self.ptr] == 0 {
-                        self.is_looping = true;
-                    } else {
-                        self.loop_stack.push(i);
-                    };
+                continue;
+            };
+
+            let result = match expr {
+                Expr::Loop(body) if self.tape.get() != 0 => {
+                    frames.push(Frame::new(body, Block::Loop));
+                    continue;
+                }
+                Expr::Loop(_) => Ok(()),
+                Expr::Procedure(body) => {
+                    let number = self.tape.get();
+                    self.procedures.insert(number, body.as_slice().into());
+                    procedures.insert(number, body);
+                    Ok(())
                 }
-                ']' => {
-                    if self.tape[self.ptr] != 0 {
-                        i = *self.loop_stack.last().unwrap();
-                    } else {
-                        self.loop_stack.pop();
+                Expr::Call => match self.procedure(&procedures, depth) {
+                    Ok(body) => {
+                        depth += 1;
+                        frames.push(Frame::new(body, Block::Procedure));
+                        continue;
                     }
+                    Err(error) => Err(error),
+                },
+                Expr::End => return Ok(()),
+                expr => self.execute_expr(expr),
+            };
+
+            match result {
+                Ok(()) => frame.index += 1,
+                Err(error) => return Err(at_frame(error, &frames)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run one expression that does not enter a block.
+    fn execute_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+        match expr {
+            Expr::IncPtr(n) => self.move_by(*n as isize)?,
+            Expr::DecPtr(n) => self.move_by(-(*n as isize))?,
+            Expr::IncData(n) => self.add_at(0, *n as u64)?,
+            Expr::DecData(n) => self.sub_at(0, *n as u64)?,
+            Expr::IncDataAt { offset, value } => self.add_at(*offset as isize, *value as u64)?,
+            Expr::DecDataAt { offset, value } => self.sub_at(*offset as isize, *value as u64)?,
+            Expr::SetZero => self.tape.set(0),
+            Expr::Scan(step) => self.scan(*step)?,
+            Expr::MulAdd { offset, factor } => self.mul_add(*offset, *factor)?,
+            Expr::Output => self.write_output()?,
+            Expr::Input => self.read_input()?,
+            Expr::Store => self.storage = self.tape.get(),
+            Expr::Load => self.tape.set(self.storage),
+            Expr::Bitwise(op) => self.bitwise(*op),
+            Expr::Loop(_) | Expr::Procedure(_) | Expr::Call | Expr::End => {
+                unreachable!("blocks and calls are run by the walk")
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The body of the procedure numbered by the current cell, to call with `depth` calls in progress.
+    fn procedure<'a>(
+        &self,
+        procedures: &HashMap<u32, &'a [Expr]>,
+        depth: usize,
+    ) -> Result<&'a [Expr], Error> {
+        let cell = self.tape.get();
+        let Some(&body) = procedures.get(&cell) else {
+            return Err(self.runtime_error(RuntimeErrorKind::UndefinedProcedure, 0, cell));
+        };
+        if depth >= self.config.language.max_depth() {
+            return Err(self.runtime_error(RuntimeErrorKind::CallDepth, 0, cell));
+        }
+
+        Ok(body)
+    }
+
+    fn bitwise(&mut self, op: BitOp) {
+        let value = op.apply(self.tape.get(), self.storage, self.config.cell_width.max());
+        self.tape.set(value);
+    }
+
+    fn read_input(&mut self) -> Result<(), Error> {
+        // Make sure any prompt is visible before blocking on input.
+        self.output.flush()?;
+
+        let mut byte = [0];
+        match self.input.read_exact(&mut byte) {
+            Ok(()) => self.tape.set(byte[0] as u32),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                self.tape.set(self.config.eof_value(self.tape.get()))
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(())
+    }
+
+    fn write_output(&mut self) -> Result<(), Error> {
+        self.output.write_all(&[self.tape.get() as u8])?;
+        Ok(())
+    }
+
+    fn scan(&mut self, step: i32) -> Result<(), Error> {
+        while self.tape.get() != 0 {
+            self.move_by(step as isize)?;
+        }
+
+        Ok(())
+    }
+
+    fn mul_add(&mut self, offset: i32, factor: i32) -> Result<(), Error> {
+        let cell = self.tape.get();
+        if cell == 0 {
+            return Ok(());
+        }
+
+        let amount = cell as u64 * factor.unsigned_abs() as u64;
+        if factor > 0 {
+            self.add_at(offset as isize, amount)
+        } else {
+            self.sub_at(offset as isize, amount)
+        }
+    }
+
+    fn move_by(&mut self, by: isize) -> Result<(), Error> {
+        self.tape
+            .move_by(by)
+            .map_err(|kind| self.runtime_error(kind, 0, self.tape.get()))
+    }
+
+    fn add_at(&mut self, offset: isize, amount: u64) -> Result<(), Error> {
+        let cell = self.cell_at(offset)?;
+        match self.config.add(cell, amount) {
+            Some(value) => self.set_at(offset, value),
+            None => Err(self.runtime_error(RuntimeErrorKind::Overflow, offset, cell)),
+        }
+    }
+
+    fn sub_at(&mut self, offset: isize, amount: u64) -> Result<(), Error> {
+        let cell = self.cell_at(offset)?;
+        match self.config.sub(cell, amount) {
+            Some(value) => self.set_at(offset, value),
+            None => Err(self.runtime_error(RuntimeErrorKind::Underflow, offset, cell)),
+        }
+    }
+
+    fn cell_at(&mut self, offset: isize) -> Result<u32, Error> {
+        self.tape
+            .get_at(offset)
+            .map_err(|kind| self.runtime_error(kind, 0, self.tape.get()))
+    }
+
+    fn set_at(&mut self, offset: isize, value: u32) -> Result<(), Error> {
+        self.tape
+            .set_at(offset, value)
+            .map_err(|kind| self.runtime_error(kind, 0, self.tape.get()))
+    }
+
+    /// Error about the cell `offset` cells away from the pointer, holding `value`.
+    fn runtime_error(&self, kind: RuntimeErrorKind, offset: isize, value: u32) -> Error {
+        Error::Runtime(RuntimeError {
+            kind,
+            instruction: 0,
+            position: None,
+            pointer: self.tape.pointer() + offset,
+            value,
+        })
+    }
+
+    /// Consume the interpreter, returning its input and output streams.
+    pub fn into_io(self) -> (R, W) {
+        (self.input, self.output)
+    }
+}
+
+impl Default for BrainFuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Report a fault where `frames` stopped, counting expressions like the compiler spans them.
+///
+/// Faults inside a procedure body are put on the call the program made, as the body
+/// may come from anywhere.
+fn at_frame(error: Error, frames: &[Frame]) -> Error {
+    let outer = frames
+        .iter()
+        .position(|frame| frame.block == Block::Procedure)
+        .unwrap_or(frames.len());
+
+    // Every block below the top adds the loop that holds the next one.
+    let instruction = frames[..outer]
+        .iter()
+        .map(|frame| node_count(&frame.exprs[..frame.index]) + 1)
+        .sum::<usize>();
+
+    match error {
+        Error::Runtime(error) => Error::Runtime(RuntimeError {
+            instruction: instruction - 1,
+            ..error
+        }),
+        error => error,
+    }
+}
+
+/// Report a fault from inside a procedure body at the call that ran it.
+fn on_call(error: Error) -> Error {
+    match error {
+        Error::Runtime(error) => Error::Runtime(RuntimeError {
+            instruction: 0,
+            ..error
+        }),
+        error => error,
+    }
+}
+
+fn shift_instruction(mut error: Error, by: usize) -> Error {
+    if let Error::Runtime(error) = &mut error {
+        error.instruction += by;
+    }
+
+    error
+}
+
+/// Run `program` with the given input and collect everything it outputs.
+pub fn run(program: &str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    run_with_config(program, input, Config::default())
+}
+
+/// Same as [`run`], with a custom interpreter configuration.
+pub fn run_with_config(program: &str, input: &[u8], config: Config) -> Result<Vec<u8>, Error> {
+    let mut brainfuck = BrainFuck::with_config(config, input, vec![]);
+    brainfuck.compile(program)?;
+
+    let (_, output) = brainfuck.into_io();
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiler::extended_corpus;
+    use config::{CellWidth, EofMode, Language, Overflow};
+    use tape::TapeMode;
+
+    #[test]
+    fn should_execute_expression_tree() {
+        use Expr::*;
+
+        // Three times two, added to the second cell by a loop.
+        let exprs = [
+            IncData(3),
+            Loop(vec![DecData(1), IncPtr(1), IncData(2), DecPtr(1)]),
+            IncPtr(1),
+        ];
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        brainfuck.execute(&exprs).unwrap();
+
+        assert_eq!(brainfuck.tape.get(), 6);
+    }
+
+    #[test]
+    fn should_print_hello_world() {
+        let program = include_str!("../scripts/hello_world.bf");
+        assert_eq!(run(program, b"").unwrap(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn should_copy_input_bytes_exactly() {
+        let input = [0xff, b'\n', 0x00, 0xc3, 0xa9, b'\r'];
+        let program = ",[.,]";
+
+        // The loop stops at the embedded zero byte.
+        assert_eq!(run(program, &input).unwrap(), [0xff, b'\n']);
+        assert_eq!(
+            run(",.,.,.,.", &input[3..]).unwrap(),
+            [0xc3, 0xa9, b'\r', b'\r']
+        );
+    }
+
+    #[test]
+    fn should_apply_eof_mode() {
+        let config = |eof| Config {
+            eof,
+            ..Default::default()
+        };
+        let input = b"line one\nline two\n";
+
+        // Each program echoes its input, relying on a different end of input value.
+        let programs = [
+            (EofMode::Zero, ",[.,]"),
+            (EofMode::Max, ",+[-.,+]"),
+            (EofMode::Unchanged, "-,+[-.[-]-,+]"),
+        ];
+
+        for (eof, program) in programs {
+            assert_eq!(run_with_config(program, input, config(eof)).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn should_run_rot13() {
+        let program = include_str!("../scripts/rot13.bf");
+        let output = run(program, b"Hello, World!\nabc xyz\n").unwrap();
+
+        assert_eq!(output, b"Uryyb, Jbeyq!\nnop klm\n");
+    }
+
+    #[test]
+    fn should_use_wide_cells() {
+        // Builds 256 in the second cell, then prints 'y' only if it is not zero.
+        let program = "++++++++++++++++[>++++++++++++++++<-]>[>+++++++++++[<+++++++++++>-]<.[-]]";
+        let config = |cell_width| Config {
+            cell_width,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            run_with_config(program, b"", config(CellWidth::U8)).unwrap(),
+            b""
+        );
+        assert_eq!(
+            run_with_config(program, b"", config(CellWidth::U16)).unwrap(),
+            b"y"
+        );
+    }
+
+    #[test]
+    fn should_report_overflow_position() {
+        let config = Config {
+            overflow: Overflow::Error,
+            ..Default::default()
+        };
+        let error = run_with_config("+[>-]", b"", config).unwrap_err();
+
+        match error {
+            Error::Runtime(error) => {
+                assert_eq!(error.kind, RuntimeErrorKind::Underflow);
+                assert_eq!(error.instruction, 3);
+                assert_eq!(error.position, Some(3));
+                assert_eq!(error.pointer, 1);
+            }
+            error => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    fn should_give_same_output_when_optimized() {
+        let programs = [
+            include_str!("../scripts/hello_world.bf"),
+            include_str!("../scripts/rot13.bf"),
+            "++++++[>++++++++<-]>[->+>++>+++<<<]>.>.>.>[-]<<<<+[>]<.",
+        ];
+        let optimized = Config {
+            optimize: true,
+            ..Default::default()
+        };
+
+        for program in programs {
+            let input = b"Some input\n";
+            assert_eq!(
+                run_with_config(program, input, optimized).unwrap(),
+                run(program, input).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn should_give_same_output_with_bytecode() {
+        let programs = [
+            include_str!("../scripts/hello_world.bf"),
+            include_str!("../scripts/rot13.bf"),
+        ];
+
+        for optimize in [false, true] {
+            let config = Config {
+                engine: Engine::Bytecode,
+                optimize,
+                ..Default::default()
+            };
+
+            for program in programs {
+                let input = b"Some input\n";
+                assert_eq!(
+                    run_with_config(program, input, config).unwrap(),
+                    run(program, input).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_call_pbrain_procedures() {
+        // Procedure 1 adds two to the next cell, procedure 2 calls it twice.
+        let program = "+(>++<)+(-::+):>.";
+
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            for optimize in [false, true] {
+                let config = Config {
+                    engine,
+                    optimize,
+                    language: "pbrain".parse().unwrap(),
+                    ..Default::default()
+                };
+                assert_eq!(run_with_config(program, b"", config).unwrap(), [4]);
+            }
+        }
+
+        // Plain brainfuck reads the extra commands as comments.
+        assert_eq!(run(program, b"").unwrap(), [2]);
+    }
+
+    #[test]
+    fn should_run_extended_commands() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            for optimize in [false, true] {
+                let config = Config {
+                    engine,
+                    optimize,
+                    language: Language::Extended,
+                    ..Default::default()
+                };
+
+                for (program, output) in extended_corpus() {
+                    assert_eq!(
+                        run_with_config(program, b"", config).unwrap(),
+                        output,
+                        "output differs for {program}"
+                    );
+                }
+            }
+        }
+
+        assert_eq!(run("+++$>+++++|.", b"").unwrap(), [5]);
+    }
+
+    #[test]
+    fn should_keep_procedures_between_programs() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let config = Config {
+                engine,
+                language: "pbrain".parse().unwrap(),
+                ..Default::default()
+            };
+            let mut brainfuck = BrainFuck::with_config(config, &b""[..], vec![]);
+            brainfuck.continue_with("+(>+<)").unwrap();
+            brainfuck.continue_with(":>.").unwrap();
+
+            let (_, output) = brainfuck.into_io();
+            assert_eq!(output, [1]);
+        }
+    }
+
+    #[test]
+    fn should_limit_call_depth() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let config = Config {
+                engine,
+                language: Language::Pbrain { max_depth: 8 },
+                ..Default::default()
+            };
+
+            let kind = |program| match run_with_config(program, b"", config) {
+                Err(Error::Runtime(error)) => error.kind,
+                result => panic!("unexpected result: {result:?}"),
+            };
+
+            assert_eq!(kind("+(:):"), RuntimeErrorKind::CallDepth);
+            assert_eq!(kind("+(:)+:"), RuntimeErrorKind::UndefinedProcedure);
+        }
+    }
+
+    #[test]
+    fn should_reach_default_call_depth_without_overflowing() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let config = Config {
+                engine,
+                language: "pbrain".parse().unwrap(),
+                ..Default::default()
+            };
+
+            // Each call is made from inside two loops of the body that called it.
+            match run_with_config("+([[:]]):", b"", config) {
+                Err(Error::Runtime(error)) => {
+                    assert_eq!(error.kind, RuntimeErrorKind::CallDepth);
+                    assert_eq!(error.position, Some(8));
                 }
-                _ => (),
+                result => panic!("unexpected result: {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn should_report_position_from_bytecode() {
+        let config = Config {
+            engine: Engine::Bytecode,
+            overflow: Overflow::Error,
+            ..Default::default()
+        };
+        let error = run_with_config("+[>-]", b"", config).unwrap_err();
+
+        match error {
+            Error::Runtime(error) => {
+                assert_eq!(error.kind, RuntimeErrorKind::Underflow);
+                assert_eq!(error.instruction, 3);
+                assert_eq!(error.position, Some(3));
             }
+            error => panic!("unexpected error: {error}"),
+        }
+    }
 
-            i += 1;
+    #[test]
+    fn should_report_position_when_optimized() {
+        let config = Config {
+            overflow: Overflow::Error,
+            optimize: true,
+            ..Default::default()
+        };
+        let error = run_with_config("++[>+++<-]>[->-<]", b"", config).unwrap_err();
+
+        match error {
+            Error::Runtime(error) => {
+                assert_eq!(error.kind, RuntimeErrorKind::Underflow);
+                assert_eq!(error.position, Some(11));
+                assert_eq!(error.pointer, 2);
+            }
+            error => panic!("unexpected error: {error}"),
         }
     }
+
+    #[test]
+    fn should_report_pointer_out_of_bounds() {
+        let error = run("+>+<<", b"").unwrap_err();
+
+        match error {
+            Error::Runtime(error) => {
+                assert_eq!(error.kind, RuntimeErrorKind::PointerUnderflow);
+                assert_eq!(error.position, Some(3));
+                assert_eq!(error.pointer, 1);
+            }
+            error => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    fn should_fault_with_and_without_optimizer() {
+        for (config, program, input) in codegen::faults() {
+            for optimize in [false, true] {
+                let config = Config { optimize, ..config };
+                let (_, finished) = codegen::interpret(program, input, config);
+                assert!(!finished, "{program} ran to its end");
+            }
+        }
+    }
+
+    #[test]
+    fn should_move_left_of_origin_on_bidirectional_tape() {
+        let config = Config {
+            tape: TapeMode::Bidirectional,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            run_with_config("<<++++++++[>++++++<-]>+.", b"", config).unwrap(),
+            b"1"
+        );
+    }
 }
@@ -0,0 +1,498 @@
+use brainfuck_rs::{
+    codegen::{self, Target},
+    compiler::{validate_with_language, Compiler, Span},
+    config::Config,
+    debugger::{Debugger, Stop},
+    dialect::{self, Translation},
+    error::{DialectError, Error},
+    formatter::{self, Style},
+    generator::{self, Effort},
+    optimizer::optimize,
+    program::{Outcome, Program},
+    repl::Repl,
+    snapshot::Snapshot,
+    *,
+};
+use clap::Parser;
+use cli::Commands;
+
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{self, stdin, stdout, BufRead, Read, Stdout, Write},
+    path::Path,
+    process::exit,
+};
+
+mod cli;
+
+/// The program stopped with a runtime error.
+const RUNTIME_ERROR: i32 = 1;
+
+/// The program does not parse or cannot be translated, clap uses the same code for bad arguments.
+const PARSE_ERROR: i32 = 2;
+
+/// A file or stream could not be read or written.
+const IO_ERROR: i32 = 3;
+
+/// The run reached `--max-steps` before the program finished.
+const SUSPENDED: i32 = 4;
+
+fn main() {
+    let cli = cli::Cli::parse();
+
+    match cli.command {
+        Commands::Run {
+            input,
+            max_steps,
+            snapshot,
+            resume: Some(path),
+            ..
+        } => resume_program(&path, input.as_deref(), max_steps, snapshot.as_deref()),
+        Commands::Run {
+            source,
+            options,
+            input,
+            max_steps,
+            snapshot,
+            resume: None,
+        } => {
+            let loaded = load_program(&source);
+            let input = open_input(input.as_deref()).unwrap_or_else(|error| fail(error.into()));
+            let mut brainfuck = BrainFuck::with_config(options.config(), input, stdout());
+
+            if max_steps.is_some() || snapshot.is_some() {
+                let mut program = brainfuck
+                    .load(loaded.program())
+                    .unwrap_or_else(|error| fail(error.into()));
+                let result =
+                    run_program(&mut brainfuck, &mut program, max_steps, snapshot.as_deref());
+                if let Err(error) = result {
+                    fail(loaded.locate(error));
+                }
+            } else if let Err(error) = brainfuck.compile(loaded.program()) {
+                fail(loaded.locate(error));
+            }
+        }
+        Commands::Resume {
+            path,
+            input,
+            max_steps,
+            snapshot,
+        } => resume_program(&path, input.as_deref(), max_steps, snapshot.as_deref()),
+        Commands::Convert { source, to } => {
+            let program = source.read().unwrap_or_else(|error| fail(error.into()));
+            let from = source.dialect().unwrap_or_else(|error| fail_dialect(error));
+            let to = cli::load_dialect(&to).unwrap_or_else(|error| fail_dialect(error));
+
+            println!("{}", dialect::convert(&program, &from, &to));
+        }
+        Commands::Check { source, language } => {
+            let program = read_program(&source);
+            if let Err(error) = validate_with_language(&program, language) {
+                fail(error.into());
+            }
+        }
+        Commands::Fmt {
+            source,
+            language,
+            width,
+            indent,
+            minify,
+        } => {
+            let program = read_program(&source);
+            let result = if minify {
+                formatter::minify(&program, language).map(|program| program + "\n")
+            } else {
+                formatter::format(&program, language, Style { width, indent })
+            };
+
+            match result {
+                Ok(program) => print!("{program}"),
+                Err(error) => fail(error.into()),
+            }
+        }
+        Commands::Emit {
+            target,
+            source,
+            options,
+        } => {
+            let program = read_program(&source);
+            emit(target, &program, &options.config());
+        }
+        Commands::Debug {
+            source,
+            options,
+            input,
+        } => {
+            // Commands come from stdin, so the program comes from elsewhere
+            // and reads its input from a file.
+            if source.is_stdin() {
+                exit_with(
+                    "give the program to debug as a file or with '-e'",
+                    PARSE_ERROR,
+                );
+            }
+            let loaded = load_program(&source);
+
+            let input: Box<dyn Read> = match input {
+                Some(path) => Box::new(File::open(path).unwrap_or_else(|error| fail(error.into()))),
+                None => Box::new(&b""[..]),
+            };
+            run_debugger(&loaded, options.config(), input);
+        }
+        Commands::Profile {
+            source,
+            options,
+            input,
+            limit,
+        } => {
+            let loaded = load_program(&source);
+            let input = open_input(input.as_deref()).unwrap_or_else(|error| fail(error.into()));
+
+            // Count the commands as written, not what the optimizer made of them.
+            let config = Config {
+                optimize: false,
+                ..options.config()
+            };
+            run_profiler(
+                &mut BrainFuck::with_config(config, input, stdout()),
+                &loaded,
+                limit,
+            );
+        }
+        Commands::Generate {
+            text,
+            input,
+            search,
+        } => {
+            let text = match text {
+                Some(text) => text.into_bytes(),
+                None => {
+                    let mut text = vec![];
+                    open_input(input.as_deref())
+                        .and_then(|mut input| input.read_to_end(&mut text))
+                        .unwrap_or_else(|error| fail(error.into()));
+                    text
+                }
+            };
+
+            let effort = if search {
+                Effort::Search
+            } else {
+                Effort::Quick
+            };
+            println!("{}", generator::generate(&text, effort));
+        }
+        Commands::Repl { options } => {
+            let mut repl = Repl::new(options.config(), stdin().lock(), stdout());
+            if let Err(error) = repl.run() {
+                fail(error.into());
+            }
+        }
+    }
+}
+
+/// A program read from the command line.
+struct Loaded {
+    /// The program as written, which positions are reported against.
+    source: String,
+
+    /// The program as brainfuck, unless it was written in brainfuck.
+    translation: Option<Translation>,
+}
+
+impl Loaded {
+    /// The brainfuck to run.
+    fn program(&self) -> &str {
+        match &self.translation {
+            Some(translation) => &translation.program,
+            None => &self.source,
+        }
+    }
+
+    /// Where `span` of the brainfuck is in the source.
+    fn source_span(&self, span: Span) -> Span {
+        match &self.translation {
+            Some(translation) => translation.source_span(span),
+            None => span,
+        }
+    }
+
+    fn source_position(&self, position: usize) -> usize {
+        let span = Span {
+            start: position,
+            end: position + 1,
+        };
+        self.source_span(span).start
+    }
+
+    /// Where the first command at or after `position` in the source is in the brainfuck.
+    fn program_position(&self, position: usize) -> usize {
+        match &self.translation {
+            Some(translation) => translation.program_position(position),
+            None => position,
+        }
+    }
+
+    /// Point a runtime error at the source rather than the brainfuck.
+    fn locate(&self, error: Error) -> Error {
+        match error {
+            Error::Runtime(mut error) => {
+                error.position = error
+                    .position
+                    .map(|position| self.source_position(position));
+                error.into()
+            }
+            error => error,
+        }
+    }
+}
+
+/// Read the program from `source`, as plain brainfuck.
+fn read_program(source: &cli::Source) -> String {
+    let loaded = load_program(source);
+    match loaded.translation {
+        Some(translation) => translation.program,
+        None => loaded.source,
+    }
+}
+
+/// Read the program from `source`, translating it from its dialect.
+fn load_program(source: &cli::Source) -> Loaded {
+    let program = source.read().unwrap_or_else(|error| fail(error.into()));
+
+    // Plain brainfuck keeps its comments, so positions match the file.
+    if source.dialect == "brainfuck" {
+        return Loaded {
+            source: program,
+            translation: None,
+        };
+    }
+
+    let dialect = source.dialect().unwrap_or_else(|error| fail_dialect(error));
+
+    // Report unmatched brackets where they are in the dialect source, not the translation.
+    if let Err(error) = dialect.compile(&program) {
+        fail(error.into());
+    }
+    Loaded {
+        translation: Some(dialect.translation(&program)),
+        source: program,
+    }
+}
+
+/// The file at `path`, or stdin without one.
+fn open_input(path: Option<&Path>) -> io::Result<Box<dyn Read>> {
+    match path {
+        Some(path) => Ok(Box::new(File::open(path)?)),
+        None => Ok(Box::new(stdin())),
+    }
+}
+
+/// Pick up the run saved at `path`, with the configuration it was saved with.
+fn resume_program(
+    path: &Path,
+    input: Option<&Path>,
+    max_steps: Option<u64>,
+    snapshot: Option<&Path>,
+) {
+    let bytes = fs::read(path).unwrap_or_else(|error| fail(error.into()));
+    let state = Snapshot::from_bytes(&bytes).unwrap_or_else(|error| exit_with(error, IO_ERROR));
+    let input = open_input(input).unwrap_or_else(|error| fail(error.into()));
+
+    let (mut brainfuck, mut program) = state.restore(input, stdout());
+    if let Err(error) = run_program(&mut brainfuck, &mut program, max_steps, snapshot) {
+        fail(error);
+    }
+}
+
+/// Run `program` for at most `max_steps`, saving a snapshot to `snapshot` when it stops early.
+fn run_program<R: Read>(
+    brainfuck: &mut BrainFuck<R, Stdout>,
+    program: &mut Program,
+    max_steps: Option<u64>,
+    snapshot: Option<&Path>,
+) -> Result<(), Error> {
+    match brainfuck.resume(program, max_steps.unwrap_or(u64::MAX)) {
+        Ok(Outcome::Finished) => Ok(()),
+        Ok(Outcome::Suspended) => {
+            eprintln!("stopped after {} steps", program.steps());
+
+            if let Some(path) = snapshot {
+                let result = brainfuck
+                    .snapshot(program)
+                    .map_err(|error| error.to_string())
+                    .and_then(|snapshot| snapshot.as_bytes().map_err(|error| error.to_string()))
+                    .and_then(|bytes| fs::write(path, bytes).map_err(|error| error.to_string()));
+
+                match result {
+                    Ok(()) => eprintln!("saved snapshot to {}", path.display()),
+                    Err(error) => exit_with(error, IO_ERROR),
+                }
+            }
+            exit(SUSPENDED);
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn run_profiler<R: Read, W: Write>(compiler: &mut BrainFuck<R, W>, loaded: &Loaded, limit: usize) {
+    let result = compiler
+        .load(loaded.program())
+        .map_err(Error::from)
+        .and_then(|mut program| compiler.profile(&mut program));
+
+    // Keep the report apart from the program's own output.
+    match result {
+        Ok(mut profile) => {
+            for profile in &mut profile.loops {
+                profile.span = loaded.source_span(profile.span);
+            }
+            eprint!("{}", profile.report(&loaded.source, limit))
+        }
+        Err(error) => fail(loaded.locate(error)),
+    }
+}
+
+fn run_debugger(loaded: &Loaded, config: Config, input: Box<dyn Read>) {
+    let mut debugger = Debugger::new(loaded.program(), config, input, stdout())
+        .unwrap_or_else(|error| fail(error.into()));
+
+    // The translation drops comments, along with any '#' in them.
+    if loaded.translation.is_some() {
+        for (offset, _) in loaded.source.match_indices('#') {
+            debugger.add_breakpoint(loaded.program_position(offset));
+        }
+    }
+
+    show_position(&debugger, loaded);
+    let mut lines = stdin().lock().lines();
+    loop {
+        print!("(bf) ");
+        stdout().flush().unwrap();
+
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+        let mut words = line.split_whitespace();
+        let command = words.next();
+        let argument = words.next().and_then(|word| word.parse::<usize>().ok());
+
+        let stop = match command {
+            Some("s" | "step") => debugger.step(),
+            Some("n" | "next") => debugger.step_over(),
+            Some("c" | "continue") => debugger.resume(),
+            Some("b" | "break") => {
+                match argument {
+                    Some(position) => {
+                        match debugger.add_breakpoint(loaded.program_position(position)) {
+                            Some(at) => println!("breakpoint at {}", loaded.source_position(at)),
+                            None => println!("no command at or after {position}"),
+                        }
+                    }
+                    None => {
+                        let breakpoints = debugger.breakpoints().into_iter();
+                        let positions = breakpoints.map(|at| loaded.source_position(at));
+                        println!("{:?}", positions.collect::<Vec<_>>());
+                    }
+                }
+                continue;
+            }
+            Some("d" | "delete") => {
+                match argument {
+                    Some(position)
+                        if debugger.remove_breakpoint(loaded.program_position(position)) => {}
+                    _ => println!("no breakpoint there"),
+                }
+                continue;
+            }
+            Some("t" | "tape") => {
+                let pointer = debugger.pointer();
+                for (index, cell) in debugger.tape(argument.unwrap_or(4)) {
+                    let marker = if index == pointer { '>' } else { ' ' };
+                    println!("{marker} {index:>6}: {cell}");
+                }
+                continue;
+            }
+            Some("q" | "quit") => return,
+            _ => {
+                println!("commands: step, next, continue, break [<position>], delete <position>, tape [<radius>], quit");
+                continue;
+            }
+        };
+
+        match stop {
+            Ok(Stop::Finished) => {
+                println!("program finished");
+                return;
+            }
+            Ok(Stop::Breakpoint) => {
+                println!("breakpoint");
+                show_position(&debugger, loaded);
+            }
+            Ok(Stop::Step) => show_position(&debugger, loaded),
+            Err(error) => fail(loaded.locate(error)),
+        }
+    }
+}
+
+/// Print the source line of the next command with a caret under it.
+fn show_position<R: Read, W: Write>(debugger: &Debugger<R, W>, loaded: &Loaded) {
+    let Some(position) = debugger.position() else {
+        return;
+    };
+    let position = loaded.source_position(position);
+    let source = &loaded.source;
+
+    let start = source[..position].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[position..]
+        .find('\n')
+        .map_or(source.len(), |i| position + i);
+    let column = source[start..position].chars().count();
+
+    println!("at {position}: {}", &source[start..end]);
+    println!(
+        "{:>width$}",
+        "^",
+        width = format!("at {position}: ").len() + column + 1
+    );
+}
+
+fn emit(target: Target, program: &str, config: &Config) {
+    let exprs = match Compiler::with_language(program.chars(), config.language).compile() {
+        Ok(exprs) if config.optimize => optimize(exprs, config.overflow),
+        Ok(exprs) => exprs,
+        Err(error) => fail(error.into()),
+    };
+
+    match codegen::emit(target, &exprs, config) {
+        Ok(code) => print!("{code}"),
+        Err(error) => fail(error.into()),
+    }
+}
+
+/// Report `error` and exit with the code for its kind.
+fn fail(error: Error) -> ! {
+    let code = match error {
+        Error::Parse(_) | Error::Unsupported(_) => PARSE_ERROR,
+        Error::Runtime(_) => RUNTIME_ERROR,
+        Error::Io(_) => IO_ERROR,
+    };
+
+    exit_with(error, code)
+}
+
+fn fail_dialect(error: DialectError) -> ! {
+    let code = match error {
+        DialectError::Io(_) => IO_ERROR,
+        _ => PARSE_ERROR,
+    };
+
+    exit_with(error, code)
+}
+
+fn exit_with(error: impl Display, code: i32) -> ! {
+    eprintln!("{error}");
+    exit(code)
+}
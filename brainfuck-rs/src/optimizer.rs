@@ -0,0 +1,379 @@
+use std::slice::Iter;
+
+use crate::{
+    compiler::{node_count, Expr, Span},
+    config::Overflow,
+};
+
+/// Rewrite common idioms into dedicated expressions and fold
+/// pointer moves into the data operations around them.
+///
+/// Some rewrites are only exact when cells wrap, so the
+/// overflow mode the program will run with is needed.
+pub fn optimize(exprs: Vec<Expr>, overflow: Overflow) -> Vec<Expr> {
+    Optimizer::new(overflow, &[]).optimize_block(exprs)
+}
+
+/// Same as [`optimize`], also mapping the spans recorded by the compiler
+/// onto the optimized expressions.
+pub fn optimize_with_spans(
+    exprs: Vec<Expr>,
+    spans: &[Span],
+    overflow: Overflow,
+) -> (Vec<Expr>, Vec<Span>) {
+    let mut optimizer = Optimizer::new(overflow, spans);
+    let exprs = optimizer.optimize_block(exprs);
+
+    (exprs, optimizer.output)
+}
+
+struct Optimizer<'a> {
+    overflow: Overflow,
+    spans: Iter<'a, Span>,
+    output: Vec<Span>,
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(overflow: Overflow, spans: &'a [Span]) -> Self {
+        Self {
+            overflow,
+            spans: spans.iter(),
+            output: vec![],
+        }
+    }
+
+    fn optimize_block(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        let mut block = vec![];
+
+        // Pointer moves not yet emitted, with the span of the last one.
+        let mut offset = 0i32;
+        let mut move_span = Span::default();
+
+        // The move right before, zero when the last expression was not one.
+        let mut previous = 0i32;
+
+        for expr in exprs {
+            let span = self.next_span();
+
+            if let Some(by) = move_distance(&expr) {
+                // Turning straight back leaves a cell nothing touches, move there
+                // anyway so the pointer leaves a fixed tape where it would have.
+                if turns(previous, by) {
+                    self.flush_move(&mut block, &mut offset, move_span);
+                }
+
+                offset += by;
+                move_span = span;
+                previous = by;
+                continue;
+            }
+            previous = 0;
+
+            match expr {
+                Expr::IncData(value) if offset != 0 => {
+                    self.emit(&mut block, Expr::IncDataAt { offset, value }, span)
+                }
+                Expr::DecData(value) if offset != 0 => {
+                    self.emit(&mut block, Expr::DecDataAt { offset, value }, span)
+                }
+                Expr::Loop(body) => {
+                    self.flush_move(&mut block, &mut offset, move_span);
+                    self.optimize_loop(&mut block, body, span);
+                }
+                Expr::Procedure(body) => {
+                    self.flush_move(&mut block, &mut offset, move_span);
+
+                    // Like a loop, the procedure comes before its body.
+                    self.output.push(span);
+                    let body = self.optimize_block(body);
+                    block.push(Expr::Procedure(body));
+                }
+                Expr::Input
+                | Expr::Output
+                | Expr::Call
+                | Expr::End
+                | Expr::Store
+                | Expr::Load
+                | Expr::Bitwise(_) => {
+                    self.flush_move(&mut block, &mut offset, move_span);
+                    self.emit(&mut block, expr, span);
+                }
+                expr => self.emit(&mut block, expr, span),
+            }
+        }
+
+        self.flush_move(&mut block, &mut offset, move_span);
+        block
+    }
+
+    fn optimize_loop(&mut self, block: &mut Vec<Expr>, body: Vec<Expr>, span: Span) {
+        if let Some(exprs) = self.idiom(&body) {
+            // The body is gone, skip over its spans.
+            for _ in 0..node_count(&body) {
+                self.spans.next();
+            }
+
+            for expr in exprs {
+                self.emit(block, expr, span);
+            }
+
+            return;
+        }
+
+        // Record the span before optimizing, so the loop comes before its body.
+        self.output.push(span);
+
+        let body = self.optimize_block(body);
+        block.push(Expr::Loop(body));
+    }
+
+    /// Replacement for a loop with the given body, if it is a known idiom.
+    fn idiom(&self, body: &[Expr]) -> Option<Vec<Expr>> {
+        match body {
+            [Expr::DecData(1)] => Some(vec![Expr::SetZero]),
+            // With saturating or checked cells `[+]` never reaches zero.
+            [Expr::IncData(1)] if self.overflow == Overflow::Wrap => Some(vec![Expr::SetZero]),
+            [Expr::IncPtr(n)] => Some(vec![Expr::Scan(*n as i32)]),
+            [Expr::DecPtr(n)] => Some(vec![Expr::Scan(-(*n as i32))]),
+            _ => self.mul_add(body),
+        }
+    }
+
+    /// Turn a balanced loop that decrements its counter once per
+    /// iteration, like `[->+>++<<]`, into multiplications.
+    fn mul_add(&self, body: &[Expr]) -> Option<Vec<Expr>> {
+        // Net change and whether both directions were used, per offset.
+        let mut changes: Vec<(i32, i64, bool)> = vec![];
+        let mut offset = 0i32;
+        let mut previous = 0i32;
+
+        for expr in body {
+            if let Some(by) = move_distance(expr) {
+                // The multiplications would skip the cell the loop turns at.
+                if turns(previous, by) {
+                    return None;
+                }
+
+                offset += by;
+                previous = by;
+                continue;
+            }
+            previous = 0;
+
+            let change = match expr {
+                Expr::IncData(n) => *n as i64,
+                Expr::DecData(n) => -(*n as i64),
+                _ => return None,
+            };
+
+            match changes.iter_mut().find(|(o, _, _)| *o == offset) {
+                Some((_, total, mixed)) => {
+                    *mixed |= total.signum() != change.signum();
+                    *total += change;
+                }
+                None => changes.push((offset, change, false)),
+            }
+        }
+
+        if offset != 0 {
+            return None;
+        }
+
+        // Going up and down on the same cell only adds up when cells wrap.
+        if self.overflow != Overflow::Wrap && changes.iter().any(|(_, _, mixed)| *mixed) {
+            return None;
+        }
+
+        if !changes
+            .iter()
+            .any(|(offset, total, _)| *offset == 0 && *total == -1)
+        {
+            return None;
+        }
+
+        let mut exprs = changes
+            .into_iter()
+            .filter(|(offset, total, _)| *offset != 0 && *total != 0)
+            .map(|(offset, total, _)| Expr::MulAdd {
+                offset,
+                factor: total as i32,
+            })
+            .collect::<Vec<_>>();
+
+        exprs.push(Expr::SetZero);
+        Some(exprs)
+    }
+
+    fn flush_move(&mut self, block: &mut Vec<Expr>, offset: &mut i32, span: Span) {
+        let expr = match *offset {
+            0 => return,
+            n if n > 0 => Expr::IncPtr(n as u32),
+            n => Expr::DecPtr(n.unsigned_abs()),
+        };
+
+        self.emit(block, expr, span);
+        *offset = 0;
+    }
+
+    fn emit(&mut self, block: &mut Vec<Expr>, expr: Expr, span: Span) {
+        block.push(expr);
+        self.output.push(span);
+    }
+
+    fn next_span(&mut self) -> Span {
+        self.spans.next().copied().unwrap_or_default()
+    }
+}
+
+/// How far `expr` moves the pointer, if it is a move.
+fn move_distance(expr: &Expr) -> Option<i32> {
+    match expr {
+        Expr::IncPtr(n) => Some(*n as i32),
+        Expr::DecPtr(n) => Some(-(*n as i32)),
+        _ => None,
+    }
+}
+
+/// Whether a move by `by` right after one by `previous` goes back the way it came.
+fn turns(previous: i32, by: i32) -> bool {
+    previous.signum() == -by.signum() && previous != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{to_source, Compiler};
+
+    fn optimized(source: &str, overflow: Overflow) -> Vec<Expr> {
+        let exprs = Compiler::new(source.chars()).compile().unwrap();
+        optimize(exprs, overflow)
+    }
+
+    #[test]
+    fn should_recognize_idioms() {
+        use Expr::*;
+
+        assert_eq!(optimized("+[-]", Overflow::Wrap), vec![IncData(1), SetZero]);
+        assert_eq!(optimized("+[+]", Overflow::Wrap), vec![IncData(1), SetZero]);
+        assert_eq!(
+            optimized("+[+]", Overflow::Saturate),
+            vec![IncData(1), Loop(vec![IncData(1)])]
+        );
+        assert_eq!(
+            optimized("+[>][<<]", Overflow::Wrap),
+            vec![IncData(1), Scan(1), Scan(-2)]
+        );
+        assert_eq!(
+            optimized("+[->+>++<<]", Overflow::Wrap),
+            vec![
+                IncData(1),
+                MulAdd {
+                    offset: 1,
+                    factor: 1
+                },
+                MulAdd {
+                    offset: 2,
+                    factor: 2
+                },
+                SetZero
+            ]
+        );
+    }
+
+    #[test]
+    fn should_fold_pointer_moves() {
+        use Expr::*;
+
+        assert_eq!(
+            optimized("+>+>--<.", Overflow::Wrap),
+            vec![
+                IncData(1),
+                IncDataAt {
+                    offset: 1,
+                    value: 1
+                },
+                DecDataAt {
+                    offset: 2,
+                    value: 2
+                },
+                IncPtr(1),
+                Output
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_pointer_excursions() {
+        use Expr::*;
+
+        assert_eq!(
+            optimized("+>>>><<<<-", Overflow::Wrap),
+            vec![
+                IncData(1),
+                IncPtr(4),
+                DecDataAt {
+                    offset: -4,
+                    value: 1
+                },
+                DecPtr(4)
+            ]
+        );
+        assert_eq!(
+            optimized("+[->>><+<<]", Overflow::Wrap),
+            vec![
+                IncData(1),
+                Loop(vec![
+                    DecData(1),
+                    IncPtr(3),
+                    IncDataAt {
+                        offset: -1,
+                        value: 1
+                    },
+                    DecPtr(3)
+                ])
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_unbalanced_loops() {
+        use Expr::*;
+
+        assert_eq!(
+            optimized("+[->+<<]", Overflow::Wrap),
+            vec![
+                IncData(1),
+                Loop(vec![
+                    DecData(1),
+                    IncDataAt {
+                        offset: 1,
+                        value: 1
+                    },
+                    DecPtr(1)
+                ])
+            ]
+        );
+    }
+
+    #[test]
+    fn should_map_spans() {
+        let source = "+[->++<]>>.";
+        let mut compiler = Compiler::new(source.chars());
+        let exprs = compiler.compile().unwrap();
+
+        let (exprs, spans) = optimize_with_spans(exprs, compiler.spans(), Overflow::Wrap);
+        let spans = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect::<Vec<_>>();
+
+        assert_eq!(exprs.len(), spans.len());
+        assert_eq!(spans, vec!["+", "[->++<]", "[->++<]", ">>", "."]);
+    }
+
+    #[test]
+    fn should_write_back_equivalent_source() {
+        let exprs = optimized("+[->+>++<<]>[-]", Overflow::Wrap);
+        assert_eq!(to_source(&exprs), "+[->+>++<<]>[-]");
+    }
+}
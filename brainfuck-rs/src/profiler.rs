@@ -0,0 +1,180 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{Read, Write},
+};
+
+use crate::{
+    bytecode::{Chunk, Op},
+    compiler::Span,
+    error::Error,
+    program::Program,
+    BrainFuck,
+};
+
+/// Longest loop source shown in a report.
+const SNIPPET_LENGTH: usize = 40;
+
+/// Execution counts gathered while running a program.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Profile {
+    /// Executions per source position, counting a run of commands like `+++` once.
+    pub positions: BTreeMap<usize, u64>,
+
+    /// Every loop in source order.
+    pub loops: Vec<LoopProfile>,
+
+    /// Instructions executed in total.
+    pub steps: u64,
+
+    /// Leftmost and rightmost cells the pointer visited.
+    pub extent: (isize, isize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopProfile {
+    pub span: Span,
+
+    /// Times the loop was reached.
+    pub entries: u64,
+
+    /// Times the body ran, over all entries.
+    pub iterations: u64,
+}
+
+impl Profile {
+    /// Report totals and the `limit` loops with the most iterations.
+    pub fn report(&self, source: &str, limit: usize) -> String {
+        let (left, right) = self.extent;
+        let mut report = String::new();
+
+        writeln!(report, "instructions: {}", self.steps).unwrap();
+        writeln!(
+            report,
+            "tape extent: {left} to {right} ({} cells)",
+            right - left + 1
+        )
+        .unwrap();
+
+        let mut loops = self.loops.iter().collect::<Vec<_>>();
+        loops.sort_by_key(|profile| std::cmp::Reverse(profile.iterations));
+        if loops.is_empty() {
+            return report;
+        }
+
+        writeln!(report, "\nhottest loops:").unwrap();
+        writeln!(
+            report,
+            "{:>12} {:>10} {:>9}  loop",
+            "iterations", "entries", "position"
+        )
+        .unwrap();
+
+        for profile in loops.into_iter().take(limit) {
+            writeln!(
+                report,
+                "{:>12} {:>10} {:>9}  {}",
+                profile.iterations,
+                profile.entries,
+                profile.span.start,
+                snippet(&source[profile.span.start..profile.span.end])
+            )
+            .unwrap();
+        }
+
+        report
+    }
+}
+
+/// `code` on a single line, cut short when too long.
+fn snippet(code: &str) -> String {
+    let code = code.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    match code.char_indices().nth(SNIPPET_LENGTH) {
+        Some((end, _)) => format!("{}...", &code[..end]),
+        None => code,
+    }
+}
+
+impl<R: Read, W: Write> BrainFuck<R, W> {
+    /// Run `program` to its end, counting what it executes.
+    pub fn profile(&mut self, program: &mut Program) -> Result<Profile, Error> {
+        let mut counts = vec![0u64; program.chunk().ops.len()];
+        let pointer = self.tape.pointer();
+        let mut extent = (pointer, pointer);
+
+        while !program.is_finished() {
+            counts[program.pc()] += 1;
+            self.resume(program, 1)?;
+
+            let pointer = self.tape.pointer();
+            extent = (extent.0.min(pointer), extent.1.max(pointer));
+        }
+
+        let mut profile = summarize(program.chunk(), &counts);
+        profile.extent = extent;
+        Ok(profile)
+    }
+}
+
+fn summarize(chunk: &Chunk, counts: &[u64]) -> Profile {
+    let mut profile = Profile {
+        steps: counts.iter().sum(),
+        ..Default::default()
+    };
+
+    for (i, (op, count)) in chunk.ops.iter().zip(counts).enumerate() {
+        match op {
+            Op::JumpIfZero(end) => profile.loops.push(LoopProfile {
+                span: chunk.spans[i],
+                entries: *count,
+                // Every pass through the body ends at the `]`.
+                iterations: counts[*end as usize - 1],
+            }),
+            Op::JumpIfNotZero(_) => continue,
+            _ => (),
+        }
+
+        *profile.positions.entry(chunk.spans[i].start).or_default() += count;
+    }
+
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_count_loops() {
+        let source = "+++[>++[>+<-]<-]";
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        let mut program = brainfuck.load(source).unwrap();
+
+        let profile = brainfuck.profile(&mut program).unwrap();
+        let loops = profile
+            .loops
+            .iter()
+            .map(|profile| (profile.span.start, profile.entries, profile.iterations))
+            .collect::<Vec<_>>();
+
+        assert_eq!(loops, vec![(3, 1, 3), (7, 3, 6)]);
+        assert_eq!(profile.positions[&0], 1);
+        assert_eq!(profile.positions[&8], 6);
+        assert_eq!(profile.extent, (0, 2));
+        assert_eq!(profile.steps, program.steps());
+    }
+
+    #[test]
+    fn should_report_hottest_loops_first() {
+        let source = "+++[>++[>+<-]<-]";
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        let mut program = brainfuck.load(source).unwrap();
+
+        let report = brainfuck.profile(&mut program).unwrap().report(source, 1);
+
+        assert!(report.contains("tape extent: 0 to 2 (3 cells)"));
+        assert!(report.contains("6          3         7  [>+<-]"));
+        assert!(!report.contains("[>++"));
+    }
+}
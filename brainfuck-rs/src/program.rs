@@ -0,0 +1,187 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bytecode::{self, Calls, Chunk},
+    compiler::Compiler,
+    error::{Error, ParseError},
+    optimizer::optimize_with_spans,
+    BrainFuck,
+};
+
+/// How a slice of execution ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program ran to its end.
+    Finished,
+
+    /// The step budget ran out, resume to continue where it stopped.
+    Suspended,
+}
+
+/// A compiled program that remembers how far it got,
+/// so it can be run a few steps at a time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    chunk: Chunk,
+    pc: usize,
+    calls: Calls,
+    steps: u64,
+}
+
+impl Program {
+    /// Whether there is nothing left to run.
+    pub fn is_finished(&self) -> bool {
+        self.pc >= self.chunk.ops.len()
+    }
+
+    /// Index in [`Program::chunk`] of the next instruction to run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// pbrain calls in progress.
+    pub fn depth(&self) -> usize {
+        self.calls.depth()
+    }
+
+    /// Instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Byte offset in the source of the next instruction to run.
+    pub fn position(&self) -> Option<usize> {
+        self.chunk.spans.get(self.pc).map(|span| span.start)
+    }
+
+    /// The instructions being run.
+    pub fn chunk(&self) -> &Chunk {
+        &self.chunk
+    }
+}
+
+impl<R: Read, W: Write> BrainFuck<R, W> {
+    /// Compile `program` for [`BrainFuck::resume`], optimizing it
+    /// when the configuration asks for it.
+    pub fn load(&self, program: &str) -> Result<Program, ParseError> {
+        let mut compiler = Compiler::with_language(program.chars(), self.config.language);
+        let mut exprs = compiler.compile()?;
+        let mut spans = compiler.spans().to_vec();
+
+        if self.config.optimize {
+            (exprs, spans) = optimize_with_spans(exprs, &spans, self.config.overflow);
+        }
+
+        Ok(Program {
+            chunk: bytecode::lower(&exprs, &spans),
+            pc: 0,
+            calls: Calls::default(),
+            steps: 0,
+        })
+    }
+
+    /// Run at most `budget` instructions of `program`, picking up
+    /// where the previous call stopped.
+    ///
+    /// A fault leaves the program at the failing instruction.
+    pub fn resume(&mut self, program: &mut Program, budget: u64) -> Result<Outcome, Error> {
+        let result = self.run_steps(program, budget);
+        self.output.flush()?;
+
+        result.map_err(|error| match error {
+            Error::Runtime(mut error) => {
+                error.instruction = program.pc;
+                error.position = program.position();
+                error.into()
+            }
+            error => error,
+        })
+    }
+
+    fn run_steps(&mut self, program: &mut Program, budget: u64) -> Result<Outcome, Error> {
+        for _ in 0..budget {
+            let Some(op) = program.chunk.ops.get(program.pc) else {
+                return Ok(Outcome::Finished);
+            };
+
+            self.execute_op(*op, &mut program.pc, &mut program.calls)?;
+            program.steps += 1;
+        }
+
+        match program.is_finished() {
+            true => Ok(Outcome::Finished),
+            false => Ok(Outcome::Suspended),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, error::RuntimeErrorKind, tape::TapeMode};
+
+    #[test]
+    fn should_suspend_infinite_loop() {
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        let mut program = brainfuck.load("+[]").unwrap();
+
+        assert_eq!(
+            brainfuck.resume(&mut program, 1000).unwrap(),
+            Outcome::Suspended
+        );
+        assert_eq!(program.steps(), 1000);
+        assert_eq!(program.position(), Some(2));
+    }
+
+    #[test]
+    fn should_suspend_endless_scan() {
+        // Every cell is set, so `[>]` goes round the tape forever.
+        let config = Config {
+            optimize: true,
+            tape: TapeMode::Wrapping(4),
+            ..Default::default()
+        };
+        let mut brainfuck = BrainFuck::with_config(config, &b""[..], vec![]);
+        let mut program = brainfuck.load("+>+>+>+[>]").unwrap();
+
+        assert_eq!(
+            brainfuck.resume(&mut program, 100).unwrap(),
+            Outcome::Suspended
+        );
+        assert_eq!(program.steps(), 100);
+    }
+
+    #[test]
+    fn should_resume_where_it_stopped() {
+        let source = include_str!("../scripts/hello_world.bf");
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        let mut program = brainfuck.load(source).unwrap();
+
+        let mut slices = 0;
+        while brainfuck.resume(&mut program, 10).unwrap() == Outcome::Suspended {
+            slices += 1;
+        }
+
+        let (_, output) = brainfuck.into_io();
+        assert!(slices > 1);
+        assert_eq!(output, b"Hello World!\n");
+    }
+
+    #[test]
+    fn should_locate_faults() {
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        let mut program = brainfuck.load("+>+<<").unwrap();
+
+        match brainfuck.resume(&mut program, u64::MAX) {
+            Err(Error::Runtime(error)) => {
+                assert_eq!(error.kind, RuntimeErrorKind::PointerUnderflow);
+                assert_eq!(error.position, Some(3));
+                assert_eq!(error.pointer, 1);
+                assert_eq!(error.value, 1);
+            }
+            result => panic!("expected a runtime error, got {result:?}"),
+        }
+    }
+}
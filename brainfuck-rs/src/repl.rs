@@ -0,0 +1,237 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    compiler::validate_with_language, config::Config, error::Error, tape::Tape, BrainFuck,
+};
+
+/// Words that follow `:` in a meta-command.
+const COMMANDS: [&str; 6] = ["cells", "reset", "load", "width", "eof", "quit"];
+
+/// Cells shown on each side of the pointer by `:cells` without a radius.
+const DEFAULT_RADIUS: usize = 4;
+
+const HELP: &str = "\
+:cells [<radius>]          show the cells around the pointer
+:reset                     clear the tape
+:load <path>               run a file on the tape
+:width (8 | 16 | 32)       change the cell width, clearing the tape
+:eof (zero | max | unchanged)
+                           change what , stores at the end of input
+:quit                      leave";
+
+/// Reads lines of brainfuck from `input` and runs each on the same tape.
+///
+/// `,` reads from `input` too, right after the line being run.
+pub struct Repl<I, O> {
+    brainfuck: BrainFuck<I, O>,
+
+    /// Lines of a loop that is still open.
+    pending: String,
+}
+
+impl<I: BufRead, O: Write> Repl<I, O> {
+    pub fn new(config: Config, input: I, output: O) -> Self {
+        Self {
+            brainfuck: BrainFuck::with_config(config, input, output),
+            pending: String::new(),
+        }
+    }
+
+    /// Prompt for lines and run them until the input ends or `:quit`.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            let prompt = if self.pending.is_empty() {
+                "bf> "
+            } else {
+                "... "
+            };
+            write!(self.brainfuck.output, "{prompt}")?;
+            self.brainfuck.output.flush()?;
+
+            let mut line = String::new();
+            if self.brainfuck.input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            if !self.eval(line.trim_end())? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handle one line, returning whether to keep going.
+    pub fn eval(&mut self, line: &str) -> io::Result<bool> {
+        if self.pending.is_empty() {
+            if let Some(command) = line.strip_prefix(':') {
+                // pbrain calls start with `:` too, so only known words are meta-commands there.
+                let known = command
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|word| COMMANDS.contains(&word));
+
+                if known || !self.brainfuck.config.language.has_procedures() {
+                    return self.command(command);
+                }
+            }
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+
+        // Keep reading while only `[` and `(` are unmatched.
+        if let Err(error) = validate_with_language(&self.pending, self.brainfuck.config.language) {
+            if error
+                .brackets
+                .iter()
+                .all(|bracket| matches!(bracket.bracket, '[' | '('))
+            {
+                return Ok(true);
+            }
+        }
+
+        let program = std::mem::take(&mut self.pending);
+        self.execute(&program)?;
+        Ok(true)
+    }
+
+    pub fn into_io(self) -> (I, O) {
+        self.brainfuck.into_io()
+    }
+
+    fn command(&mut self, command: &str) -> io::Result<bool> {
+        let mut words = command.split_whitespace();
+        let output = &mut self.brainfuck.output;
+
+        match (words.next(), words.next()) {
+            (Some("cells"), radius) => {
+                let radius = radius.and_then(|radius| radius.parse().ok());
+                let pointer = self.brainfuck.tape.pointer();
+                let cells = self.brainfuck.tape.around(radius.unwrap_or(DEFAULT_RADIUS));
+
+                for (index, cell) in cells {
+                    let marker = if index == pointer { '>' } else { ' ' };
+                    writeln!(output, "{marker} {index:>6}: {cell}")?;
+                }
+            }
+            (Some("reset"), None) => self.reset(),
+            (Some("load"), Some(path)) => match fs::read_to_string(path) {
+                // Skip a leading comment loop, as running the file would.
+                Ok(program) => {
+                    let result = self.brainfuck.compile(&program);
+                    self.report(result)?
+                }
+                Err(error) => writeln!(output, "{path}: {error}")?,
+            },
+            (Some("width"), Some(width)) => match width.parse() {
+                Ok(width) => {
+                    // Cells may not fit the new width, start over.
+                    self.brainfuck.config.cell_width = width;
+                    self.reset();
+                }
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            (Some("eof"), Some(eof)) => match eof.parse() {
+                Ok(eof) => self.brainfuck.config.eof = eof,
+                Err(error) => writeln!(output, "{error}")?,
+            },
+            (Some("quit"), None) => return Ok(false),
+            _ => writeln!(output, "{HELP}")?,
+        }
+
+        Ok(true)
+    }
+
+    fn reset(&mut self) {
+        self.brainfuck.tape = Tape::new(self.brainfuck.config.tape);
+    }
+
+    /// Run `program`, reporting errors without leaving the loop.
+    fn execute(&mut self, program: &str) -> io::Result<()> {
+        let result = self.brainfuck.continue_with(program);
+        self.report(result)
+    }
+
+    /// Print a program's error, leaving the loop only when the streams failed.
+    fn report(&mut self, result: Result<(), Error>) -> io::Result<()> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(Error::Io(error)) => Err(error),
+            Err(error) => writeln!(self.brainfuck.output, "{error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Engine;
+
+    fn session(input: &str) -> String {
+        let mut repl = Repl::new(Config::default(), input.as_bytes(), vec![]);
+        repl.run().unwrap();
+
+        let (_, output) = repl.into_io();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn should_keep_tape_between_lines() {
+        let output = session("++++++++[>++++++++<-]\n>+.\n:cells 1\n");
+
+        assert_eq!(
+            output,
+            "bf> bf> Abf>        0: 0\n>      1: 65\n       2: 0\nbf> "
+        );
+    }
+
+    #[test]
+    fn should_continue_open_loops() {
+        let output = session("+++[\n>++<-\n]>.\n:quit\n+.\n");
+
+        assert_eq!(output, "bf> ... ... \u{6}bf> ");
+    }
+
+    #[test]
+    fn should_call_procedures_from_earlier_lines() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let config = Config {
+                engine,
+                language: "pbrain".parse().unwrap(),
+                ..Default::default()
+            };
+            let mut repl = Repl::new(config, "+(>+<)\n:>.\n:cells 0\n".as_bytes(), vec![]);
+            repl.run().unwrap();
+
+            let (_, output) = repl.into_io();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "bf> bf> \u{1}bf> >      1: 1\nbf> "
+            );
+        }
+    }
+
+    #[test]
+    fn should_skip_leading_comment_when_loading() {
+        let path =
+            std::env::temp_dir().join(format!("brainfuck-rs-repl-{}.bf", std::process::id()));
+        fs::write(&path, "[clear - it first]++.").unwrap();
+
+        let output = session(&format!("+\n:load {}\n", path.display()));
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(output, "bf> bf> \u{3}bf> ");
+    }
+
+    #[test]
+    fn should_reset_on_width_change() {
+        let output = session("-\n:width 16\n:cells 0\n-\n:cells 0\n");
+
+        assert_eq!(
+            output,
+            "bf> bf> bf> >      0: 0\nbf> bf> >      0: 65535\nbf> "
+        );
+    }
+}
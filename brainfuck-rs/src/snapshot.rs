@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    io::{self, Chain, Cursor, Read, Write},
+};
+
+use bincode::{DefaultOptions, Options};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, error::SnapshotError, program::Program, tape::Tape, BrainFuck};
+
+/// Leading bytes of every snapshot.
+const MAGIC: &[u8; 4] = b"BFSN";
+
+/// Format version written by this build, bump on any change to [`Snapshot`].
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Input of a restored interpreter, the pending input followed by the new stream.
+pub type RestoredInput<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// The full state of a suspended run, enough to continue it elsewhere.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub config: Config,
+    pub tape: Tape,
+    pub program: Program,
+
+    /// The Extended Type I storage register.
+    pub storage: u32,
+
+    /// Input that was available but not yet read by the program.
+    pub input: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Encode as the magic bytes, the format version and the state.
+    pub fn as_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend(
+            DefaultOptions::new()
+                .with_varint_encoding()
+                .serialize(self)?,
+        );
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or(SnapshotError::NotASnapshot)?;
+        let (version, state) = rest
+            .split_first_chunk::<4>()
+            .ok_or(SnapshotError::NotASnapshot)?;
+
+        match u32::from_le_bytes(*version) {
+            SNAPSHOT_VERSION => Ok(DefaultOptions::new()
+                .with_varint_encoding()
+                .deserialize(state)?),
+            version => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Rebuild the interpreter and program, reading the pending
+    /// input before anything from `input`.
+    pub fn restore<R: Read, W: Write>(
+        self,
+        input: R,
+        output: W,
+    ) -> (BrainFuck<RestoredInput<R>, W>, Program) {
+        let brainfuck = BrainFuck {
+            config: self.config,
+            tape: self.tape,
+            input: Cursor::new(self.input).chain(input),
+            output,
+            procedures: HashMap::new(),
+            procedure_ops: HashMap::new(),
+            depth: 0,
+            storage: self.storage,
+        };
+
+        (brainfuck, self.program)
+    }
+}
+
+impl<R: Read, W: Write> BrainFuck<R, W> {
+    /// Capture the interpreter running `program`.
+    ///
+    /// The rest of the input is read to its end and kept in the snapshot.
+    pub fn snapshot(&mut self, program: &Program) -> io::Result<Snapshot> {
+        let mut input = vec![];
+        self.input.read_to_end(&mut input)?;
+
+        Ok(Snapshot {
+            config: self.config,
+            tape: self.tape.clone(),
+            program: program.clone(),
+            storage: self.storage,
+            input,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Outcome;
+
+    #[test]
+    fn should_continue_from_snapshot() {
+        // Reverse the input.
+        let source = ">,[>,]<[.<]";
+        let mut brainfuck = BrainFuck::with_io(&b"abcdef"[..], vec![]);
+        let mut program = brainfuck.load(source).unwrap();
+
+        assert_eq!(
+            brainfuck.resume(&mut program, 6).unwrap(),
+            Outcome::Suspended
+        );
+        let bytes = brainfuck.snapshot(&program).unwrap().as_bytes().unwrap();
+
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(snapshot.input, b"cdef");
+
+        let (mut brainfuck, mut program) = snapshot.restore(&b""[..], vec![]);
+        assert_eq!(
+            brainfuck.resume(&mut program, u64::MAX).unwrap(),
+            Outcome::Finished
+        );
+
+        let (_, output) = brainfuck.into_io();
+        assert_eq!(output, b"fedcba");
+    }
+
+    #[test]
+    fn should_reject_other_versions() {
+        let mut brainfuck = BrainFuck::with_io(&b""[..], vec![]);
+        let program = brainfuck.load("+").unwrap();
+        let mut bytes = brainfuck.snapshot(&program).unwrap().as_bytes().unwrap();
+        bytes[4] = 1;
+
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(b"[-]"),
+            Err(SnapshotError::NotASnapshot)
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::RuntimeErrorKind;
+
+/// Length of the classic brainfuck tape.
+pub const TAPE_LENGTH: usize = 30000;
+
+/// Most cells a growing tape holds, 64 MiB of them. Moving past that
+/// is a fault, as it is at the end of a fixed tape.
+pub const MAX_TAPE_LENGTH: usize = 1 << 24;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapeMode {
+    /// A fixed number of cells starting at zero.
+    Fixed(usize),
+
+    /// Starts with the given number of cells and grows to the right as needed,
+    /// up to [`MAX_TAPE_LENGTH`] cells.
+    Growable(usize),
+
+    /// Grows in both directions, allowing negative cell indices,
+    /// up to [`MAX_TAPE_LENGTH`] cells.
+    Bidirectional,
+
+    /// A fixed number of cells, moving past either end wraps to the other.
+    Wrapping(usize),
+}
+
+impl Default for TapeMode {
+    fn default() -> Self {
+        TapeMode::Fixed(TAPE_LENGTH)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tape {
+    mode: TapeMode,
+    cells: Vec<u32>,
+
+    /// Index in `cells` of the cell at pointer zero.
+    origin: usize,
+    ptr: isize,
+}
+
+impl Tape {
+    pub fn new(mode: TapeMode) -> Self {
+        let length = match mode {
+            TapeMode::Fixed(length) | TapeMode::Growable(length) | TapeMode::Wrapping(length) => {
+                length.max(1)
+            }
+            TapeMode::Bidirectional => 1,
+        };
+
+        Self {
+            mode,
+            cells: vec![0; length],
+            origin: 0,
+            ptr: 0,
+        }
+    }
+
+    pub fn pointer(&self) -> isize {
+        self.ptr
+    }
+
+    pub fn get(&self) -> u32 {
+        self.cells[self.index()]
+    }
+
+    pub fn set(&mut self, value: u32) {
+        let index = self.index();
+        self.cells[index] = value;
+    }
+
+    /// Move the pointer `by` cells to the right, or to the left when negative.
+    pub fn move_by(&mut self, by: isize) -> Result<(), RuntimeErrorKind> {
+        self.ptr = self.resolve(self.ptr + by)?;
+        Ok(())
+    }
+
+    /// Value of the cell `offset` cells away from the pointer.
+    pub fn get_at(&mut self, offset: isize) -> Result<u32, RuntimeErrorKind> {
+        let index = self.resolve(self.ptr + offset)? + self.origin as isize;
+        Ok(self.cells[index as usize])
+    }
+
+    /// Change the cell `offset` cells away from the pointer.
+    pub fn set_at(&mut self, offset: isize, value: u32) -> Result<(), RuntimeErrorKind> {
+        let index = self.resolve(self.ptr + offset)? + self.origin as isize;
+        self.cells[index as usize] = value;
+        Ok(())
+    }
+
+    /// Cells visited so far, with the index of the leftmost one.
+    pub fn cells(&self) -> (isize, &[u32]) {
+        (-(self.origin as isize), &self.cells)
+    }
+
+    /// Cells within `radius` of the pointer that exist, with their indices.
+    pub fn around(&self, radius: usize) -> Vec<(isize, u32)> {
+        let (start, cells) = self.cells();
+        let radius = radius as isize;
+
+        (self.ptr - radius..=self.ptr + radius)
+            .filter_map(|index| {
+                let cell = cells.get(usize::try_from(index - start).ok()?)?;
+                Some((index, *cell))
+            })
+            .collect()
+    }
+
+    /// Cells and pointer of a tape that never grows, for engines
+    /// working on the memory directly.
+    #[cfg(feature = "jit")]
+    pub(crate) fn fixed_parts(&mut self) -> (&mut [u32], &mut isize) {
+        debug_assert_eq!(self.origin, 0);
+        (&mut self.cells, &mut self.ptr)
+    }
+
+    /// Check `target` against the bounds of the tape, returning the
+    /// position it ends up at and making sure that cell exists.
+    fn resolve(&mut self, target: isize) -> Result<isize, RuntimeErrorKind> {
+        let target = match self.mode {
+            TapeMode::Fixed(_) | TapeMode::Growable(_) if target < 0 => {
+                return Err(RuntimeErrorKind::PointerUnderflow)
+            }
+            TapeMode::Fixed(_) if target as usize >= self.cells.len() => {
+                return Err(RuntimeErrorKind::PointerOverflow)
+            }
+            TapeMode::Growable(_) | TapeMode::Bidirectional if !self.fits(target) => {
+                return Err(if target < -(self.origin as isize) {
+                    RuntimeErrorKind::PointerUnderflow
+                } else {
+                    RuntimeErrorKind::PointerOverflow
+                });
+            }
+            TapeMode::Wrapping(_) => target.rem_euclid(self.cells.len() as isize),
+            _ => target,
+        };
+
+        self.grow(target);
+        Ok(target)
+    }
+
+    /// Whether the tape can grow to hold `target` without going past [`MAX_TAPE_LENGTH`].
+    fn fits(&self, target: isize) -> bool {
+        let index = target + self.origin as isize;
+        let length = if index < 0 {
+            self.cells.len() + index.unsigned_abs()
+        } else {
+            self.cells.len().max(index as usize + 1)
+        };
+
+        length <= MAX_TAPE_LENGTH
+    }
+
+    fn grow(&mut self, target: isize) {
+        let index = target + self.origin as isize;
+
+        if index < 0 {
+            // Grow by at least the current size to keep repeated moves cheap.
+            let extra = index
+                .unsigned_abs()
+                .max(self.cells.len())
+                .min(MAX_TAPE_LENGTH.saturating_sub(self.cells.len()))
+                .max(index.unsigned_abs());
+            self.cells.splice(0..0, std::iter::repeat_n(0, extra));
+            self.origin += extra;
+        } else if index as usize >= self.cells.len() {
+            self.cells.resize(index as usize + 1, 0);
+        }
+    }
+
+    fn index(&self) -> usize {
+        (self.ptr + self.origin as isize) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_stay_inside_fixed_tape() {
+        let mut tape = Tape::new(TapeMode::Fixed(3));
+
+        assert_eq!(tape.move_by(-1), Err(RuntimeErrorKind::PointerUnderflow));
+        assert_eq!(tape.move_by(2), Ok(()));
+        assert_eq!(tape.move_by(1), Err(RuntimeErrorKind::PointerOverflow));
+        assert_eq!(tape.pointer(), 2);
+    }
+
+    #[test]
+    fn should_grow_to_the_right() {
+        let mut tape = Tape::new(TapeMode::Growable(2));
+
+        tape.move_by(5).unwrap();
+        tape.set(7);
+
+        assert_eq!(tape.cells(), (0, &[0, 0, 0, 0, 0, 7][..]));
+        assert_eq!(tape.move_by(-6), Err(RuntimeErrorKind::PointerUnderflow));
+    }
+
+    #[test]
+    fn should_grow_in_both_directions() {
+        let mut tape = Tape::new(TapeMode::Bidirectional);
+
+        tape.move_by(-3).unwrap();
+        tape.set(1);
+        tape.move_by(4).unwrap();
+        tape.set(2);
+        tape.move_by(-4).unwrap();
+
+        assert_eq!(tape.pointer(), -3);
+        assert_eq!(tape.get(), 1);
+
+        let (start, cells) = tape.cells();
+        assert_eq!(cells[(1 - start) as usize], 2);
+    }
+
+    #[test]
+    fn should_stop_growing_at_max_length() {
+        let max = MAX_TAPE_LENGTH as isize;
+
+        let mut tape = Tape::new(TapeMode::Growable(1));
+        assert_eq!(tape.move_by(max), Err(RuntimeErrorKind::PointerOverflow));
+
+        let mut tape = Tape::new(TapeMode::Bidirectional);
+        assert_eq!(tape.move_by(-max), Err(RuntimeErrorKind::PointerUnderflow));
+        tape.move_by(-3).unwrap();
+        assert_eq!(tape.move_by(max), Err(RuntimeErrorKind::PointerOverflow));
+        assert_eq!(tape.pointer(), -3);
+    }
+
+    #[test]
+    fn should_wrap_around() {
+        let mut tape = Tape::new(TapeMode::Wrapping(4));
+
+        tape.move_by(-1).unwrap();
+        assert_eq!(tape.pointer(), 3);
+
+        tape.move_by(6).unwrap();
+        assert_eq!(tape.pointer(), 1);
+    }
+}